@@ -0,0 +1,140 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use fnv::FnvHashMap;
+use semver::Version;
+
+use rt_result::RtResult;
+use types::DepTree;
+use config::Config;
+
+/// A cached tags file in the cache directory.
+struct CacheEntry {
+    /// the 'Cargo.toml' name of the cached source
+    name: String,
+
+    /// the version of the cached source, if it could be determined
+    version: Option<String>,
+
+    /// the kind of the cached tags, 'vi' or 'emacs'
+    kind: String,
+
+    /// the file size in bytes
+    size: u64,
+
+    /// the time since the last access in seconds
+    age: Option<u64>,
+
+    /// if the entry belongs to a source of the current dependency tree
+    live: bool
+}
+
+/// Prints every entry of the cache directory with its crate name, version,
+/// kind, size, the time since the last access and if it's in the live set,
+/// the sources of 'dep_tree'.
+pub fn list_cache(config: &Config, dep_tree: &DepTree) -> RtResult<()> {
+    // the live sources by the file stem of their cached tags, which
+    // is the same for the vi and the emacs tags of a source
+    let live_versions: FnvHashMap<String, String> = dep_tree.all_sources()
+        .filter_map(|s| {
+            let stem = s.cached_tags_file.file_stem()?.to_str()?.to_string();
+            Some((stem, s.version.to_string()))
+        })
+        .collect();
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(&config.cache_dir)? {
+        let path = entry?.path();
+        let (stem, kind) = match (path.file_stem().and_then(|s| s.to_str()), path.extension().and_then(|e| e.to_str())) {
+            (Some(stem), Some(kind)) if path.is_file() && (kind == "vi" || kind == "emacs") => (stem.to_string(), kind.to_string()),
+            _ => continue
+        };
+
+        let name = match stem.rfind('-') {
+            Some(idx) => stem[..idx].to_string(),
+            None => continue
+        };
+
+        let metadata = fs::metadata(&path)?;
+        let age = metadata.accessed().ok()
+            .and_then(|t| SystemTime::now().duration_since(t).ok())
+            .map(|d| d.as_secs());
+
+        let live_version = live_versions.get(&stem).cloned();
+        let live = live_version.is_some();
+        let version = match live_version {
+            Some(version) => Some(version),
+            None => version_from_tags(&path, &name)?
+        };
+
+        entries.push(CacheEntry { name, version, kind, size: metadata.len(), age, live });
+    }
+
+    entries.sort_by(|a, b| (&a.name, &a.version, &a.kind).cmp(&(&b.name, &b.version, &b.kind)));
+
+    // a closed pipe, e.g. of 'rusty-tags cache-list | head', ends the listing with an error
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    writeln!(stdout, "{:<30} {:<15} {:<6} {:>8} {:>6}  LIVE", "CRATE", "VERSION", "KIND", "SIZE", "AGE")?;
+    let mut total_size = 0;
+    for entry in &entries {
+        total_size += entry.size;
+        writeln!(stdout, "{:<30} {:<15} {:<6} {:>8} {:>6}  {}",
+                 entry.name,
+                 entry.version.as_deref().unwrap_or("?"),
+                 entry.kind,
+                 format_size(entry.size),
+                 entry.age.map(format_age).unwrap_or("?".to_string()),
+                 if entry.live { "yes" } else { "no" })?;
+    }
+
+    let num_live = entries.iter().filter(|e| e.live).count();
+    writeln!(stdout, "\n{} entries, {} live, {} in '{}'", entries.len(), num_live, format_size(total_size), config.cache_dir.display())?;
+    Ok(())
+}
+
+/// Tries to find the version of the crate 'name' in the source paths of the
+/// first tags of 'tags_file', which contain e.g. '/serde-1.0.197/' for crates
+/// of a registry. Compressed tags files have no version.
+fn version_from_tags(tags_file: &Path, name: &str) -> RtResult<Option<String>> {
+    let dir_prefix = format!("/{}-", name);
+    let reader = BufReader::new(File::open(tags_file)?);
+    for line in reader.split(b'\n').take(20) {
+        let line = line?;
+        let line = String::from_utf8_lossy(&line);
+        let mut start = 0;
+        while let Some(idx) = line[start..].find(&dir_prefix) {
+            let version_start = start + idx + dir_prefix.len();
+            let version = line[version_start..].split(['/', '\\']).next().unwrap_or("");
+            if Version::parse(version).is_ok() {
+                return Ok(Some(version.to_string()));
+            }
+
+            start = version_start;
+        }
+    }
+
+    Ok(None)
+}
+
+fn format_size(size: u64) -> String {
+    if size >= 1024 * 1024 {
+        format!("{:.1}M", size as f64 / (1024.0 * 1024.0))
+    } else if size >= 1024 {
+        format!("{:.1}K", size as f64 / 1024.0)
+    } else {
+        format!("{}B", size)
+    }
+}
+
+fn format_age(secs: u64) -> String {
+    if secs >= 24 * 60 * 60 {
+        format!("{}d", secs / (24 * 60 * 60))
+    } else if secs >= 60 * 60 {
+        format!("{}h", secs / (60 * 60))
+    } else {
+        format!("{}m", secs / 60)
+    }
+}
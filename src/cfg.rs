@@ -0,0 +1,250 @@
+//! A best-effort evaluation of the '#[cfg(...)]' attributes for '--active-cfg-only',
+//! which only knows the target predicates 'target_os', 'target_arch', 'target_family',
+//! 'unix' and 'windows' combined by 'all', 'any' and 'not'.
+
+use std::env;
+
+use rt_result::RtResult;
+
+/// The target for which the cfg attributes are evaluated.
+#[derive(Debug)]
+pub struct CfgTarget {
+    os: String,
+    arch: String,
+    family: Option<String>
+}
+
+/// The operating systems of target triples, which aren't always their third component.
+const TARGET_OSES: [&str; 17] = ["linux", "android", "windows", "macos", "darwin", "ios", "freebsd", "netbsd",
+                                 "openbsd", "dragonfly", "solaris", "illumos", "redox", "fuchsia", "haiku", "emscripten",
+                                 "none"];
+
+impl CfgTarget {
+    /// The target rusty-tags is running on.
+    pub fn host() -> CfgTarget {
+        CfgTarget {
+            os: env::consts::OS.to_string(),
+            arch: env::consts::ARCH.to_string(),
+            family: Some(env::consts::FAMILY.to_string()).filter(|f| ! f.is_empty())
+        }
+    }
+
+    /// The target of a target triple like 'x86_64-pc-windows-msvc'.
+    pub fn from_triple(triple: &str) -> RtResult<CfgTarget> {
+        let components: Vec<&str> = triple.split('-').collect();
+        if components.len() < 2 || components.iter().any(|c| c.is_empty()) {
+            return Err(format!("Invalid target triple '{}', expected e.g. 'x86_64-unknown-linux-gnu'!", triple).into());
+        }
+
+        let arch = match components[0] {
+            a if a.starts_with("aarch64") || a.starts_with("arm64") => "aarch64",
+            a if a.starts_with("arm") || a.starts_with("thumb") => "arm",
+            "i386" | "i586" | "i686" => "x86",
+            a if a.starts_with("riscv64") => "riscv64",
+            a if a.starts_with("riscv32") => "riscv32",
+            a if a.starts_with("powerpc64") => "powerpc64",
+            a => a
+        };
+
+        let os = match components[1..].iter().find(|c| TARGET_OSES.contains(c)) {
+            Some(&"darwin") => "macos",
+            Some(os) => os,
+            None => components.get(2).unwrap_or(&components[1])
+        };
+
+        let family = match os {
+            "windows" => Some("windows"),
+            _ if arch.starts_with("wasm") => Some("wasm"),
+            "none" | "unknown" | "uefi" => None,
+            _ => Some("unix")
+        };
+
+        Ok(CfgTarget { os: os.to_string(), arch: arch.to_string(), family: family.map(String::from) })
+    }
+
+    /// Evaluates the predicate 'cfg' of a cfg attribute, e.g. 'target_os = "windows"'.
+    /// Returns 'None' if the predicate depends on anything else than the target,
+    /// like a feature, or couldn't be parsed.
+    pub fn is_active(&self, cfg: &str) -> Option<bool> {
+        let mut parser = CfgParser { input: cfg, pos: 0 };
+        let active = parser.predicate(self)?;
+        if parser.rest().is_empty() {
+            active
+        } else {
+            None
+        }
+    }
+
+    fn matches(&self, key: &str, value: Option<&str>) -> Option<bool> {
+        match (key, value) {
+            ("unix", None) | ("windows", None) => Some(self.family.as_deref() == Some(key)),
+            ("target_os", Some(value)) => Some(self.os == value),
+            ("target_arch", Some(value)) => Some(self.arch == value),
+            ("target_family", Some(value)) => Some(self.family.as_deref() == Some(value)),
+            _ => None
+        }
+    }
+}
+
+/// Parses and evaluates a cfg predicate. The outer 'Option' is 'None' for
+/// a parse error, the inner one for a predicate which isn't about the target.
+struct CfgParser<'a> {
+    input: &'a str,
+    pos: usize
+}
+
+impl<'a> CfgParser<'a> {
+    fn rest(&self) -> &'a str {
+        self.input[self.pos..].trim_start()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let rest = self.rest();
+        if rest.starts_with(c) {
+            self.pos = self.input.len() - rest.len() + c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        let rest = self.rest();
+        let len = rest.find(|c: char| ! (c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        if len == 0 {
+            return None;
+        }
+
+        self.pos = self.input.len() - rest.len() + len;
+        Some(&rest[..len])
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        if ! self.eat('"') {
+            return None;
+        }
+
+        let rest = &self.input[self.pos..];
+        let len = rest.find('"')?;
+        self.pos += len + 1;
+        Some(&rest[..len])
+    }
+
+    fn predicate(&mut self, target: &CfgTarget) -> Option<Option<bool>> {
+        let ident = self.ident()?;
+        if self.eat('=') {
+            let value = self.string()?;
+            return Some(target.matches(ident, Some(value)));
+        }
+
+        if ! self.eat('(') {
+            return Some(target.matches(ident, None));
+        }
+
+        let mut args = Vec::new();
+        while ! self.eat(')') {
+            args.push(self.predicate(target)?);
+            if ! self.eat(',') && ! self.rest().starts_with(')') {
+                return None;
+            }
+        }
+
+        let active = match ident {
+            "not" if args.len() == 1 => args[0].map(|a| ! a),
+            "all" if args.contains(&Some(false)) => Some(false),
+            "all" if args.iter().all(|a| *a == Some(true)) => Some(true),
+            "any" if args.contains(&Some(true)) => Some(true),
+            "any" if args.iter().all(|a| *a == Some(false)) => Some(false),
+            "all" | "any" => None,
+            _ => return None
+        };
+
+        Some(active)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(triple: &str) -> CfgTarget {
+        CfgTarget::from_triple(triple).unwrap()
+    }
+
+    #[test]
+    fn from_triple_of_valid_triples() {
+        let linux = target("x86_64-unknown-linux-gnu");
+        assert_eq!((linux.arch.as_str(), linux.os.as_str(), linux.family.as_deref()), ("x86_64", "linux", Some("unix")));
+
+        let windows = target("i686-pc-windows-msvc");
+        assert_eq!((windows.arch.as_str(), windows.os.as_str(), windows.family.as_deref()), ("x86", "windows", Some("windows")));
+
+        let macos = target("aarch64-apple-darwin");
+        assert_eq!((macos.arch.as_str(), macos.os.as_str(), macos.family.as_deref()), ("aarch64", "macos", Some("unix")));
+
+        let android = target("armv7-linux-androideabi");
+        assert_eq!((android.arch.as_str(), android.os.as_str()), ("arm", "linux"));
+
+        let wasm = target("wasm32-unknown-unknown");
+        assert_eq!((wasm.arch.as_str(), wasm.os.as_str(), wasm.family.as_deref()), ("wasm32", "unknown", Some("wasm")));
+
+        let bare = target("thumbv7em-none-eabihf");
+        assert_eq!((bare.arch.as_str(), bare.os.as_str(), bare.family.as_deref()), ("arm", "none", None));
+    }
+
+    #[test]
+    fn from_triple_of_invalid_triples() {
+        assert!(CfgTarget::from_triple("").is_err());
+        assert!(CfgTarget::from_triple("x86_64").is_err());
+        assert!(CfgTarget::from_triple("x86_64--linux").is_err());
+        assert!(CfgTarget::from_triple("-unknown-linux-gnu").is_err());
+    }
+
+    #[test]
+    fn is_active_of_target_predicates() {
+        let linux = target("x86_64-unknown-linux-gnu");
+        assert_eq!(linux.is_active("unix"), Some(true));
+        assert_eq!(linux.is_active("windows"), Some(false));
+        assert_eq!(linux.is_active("target_os = \"linux\""), Some(true));
+        assert_eq!(linux.is_active("target_os=\"macos\""), Some(false));
+        assert_eq!(linux.is_active("target_arch = \"x86_64\""), Some(true));
+        assert_eq!(linux.is_active("target_family = \"windows\""), Some(false));
+    }
+
+    #[test]
+    fn is_active_of_combined_predicates() {
+        let linux = target("x86_64-unknown-linux-gnu");
+        assert_eq!(linux.is_active("not(windows)"), Some(true));
+        assert_eq!(linux.is_active("all(unix, target_arch = \"x86_64\")"), Some(true));
+        assert_eq!(linux.is_active("all(unix, target_arch = \"arm\")"), Some(false));
+        assert_eq!(linux.is_active("any(windows, target_os = \"linux\")"), Some(true));
+        assert_eq!(linux.is_active("any(windows, target_os = \"macos\",)"), Some(false));
+        assert_eq!(linux.is_active("all()"), Some(true));
+        assert_eq!(linux.is_active("any()"), Some(false));
+    }
+
+    #[test]
+    fn is_active_of_predicates_not_about_the_target() {
+        let linux = target("x86_64-unknown-linux-gnu");
+        assert_eq!(linux.is_active("test"), None);
+        assert_eq!(linux.is_active("feature = \"serde\""), None);
+        assert_eq!(linux.is_active("not(feature = \"serde\")"), None);
+        assert_eq!(linux.is_active("all(unix, feature = \"serde\")"), None);
+
+        // decided by the target predicates alone
+        assert_eq!(linux.is_active("all(windows, feature = \"serde\")"), Some(false));
+        assert_eq!(linux.is_active("any(unix, feature = \"serde\")"), Some(true));
+    }
+
+    #[test]
+    fn is_active_of_invalid_predicates() {
+        let linux = target("x86_64-unknown-linux-gnu");
+        assert_eq!(linux.is_active(""), None);
+        assert_eq!(linux.is_active("all(unix"), None);
+        assert_eq!(linux.is_active("target_os = linux"), None);
+        assert_eq!(linux.is_active("target_os = \"linux"), None);
+        assert_eq!(linux.is_active("unix windows"), None);
+        assert_eq!(linux.is_active("not(unix, windows)"), None);
+        assert_eq!(linux.is_active("cfg_if(unix)"), None);
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tempfile::NamedTempFile;
+
+use rt_result::RtResult;
+use config::Config;
+use files::rust_files;
+use tags::{create_tags, move_tags};
+
+/// Creates the tags of the rust sources of the rustup toolchain components
+/// of '--index-component', e.g. of 'rustc-dev' for the compiler sources.
+///
+/// Like the tags of the standard library, the tags of a component are written into
+/// its source directory in the sysroot and are only created if they're missing.
+pub fn update_component_tags(config: &Config) -> RtResult<()> {
+    if config.index_components.is_empty() {
+        return Ok(());
+    }
+
+    let sysroot = sysroot(config)?;
+    for component in &config.index_components {
+        let src_dir = component_source_dir(&sysroot, component)?;
+        let tags_file = src_dir.join(config.tags_spec.file_name());
+        if tags_file.is_file() && ! config.force_recreate {
+            verbose!(config, "\nUsing existing tags of component '{}' at '{}'", component, tags_file.display());
+            continue;
+        }
+
+        info!(config, "Creating tags for the toolchain component '{}' ...", component);
+
+        let tmp_tags = NamedTempFile::new_in(&src_dir)?;
+        create_tags(config, &[&src_dir], tmp_tags.path())?;
+        move_tags(config, tmp_tags.path(), &tags_file)?;
+    }
+
+    Ok(())
+}
+
+/// The sysroot of the toolchain used for the start directory, which might be overridden.
+fn sysroot(config: &Config) -> RtResult<PathBuf> {
+    let output = Command::new("rustc")
+        .current_dir(&config.start_dir)
+        .arg("--print")
+        .arg("sysroot")
+        .output()
+        .map_err(|err| format!("'rustc' execution failed: {}\nIs 'rustc' correctly installed?", err))?;
+
+    if ! output.status.success() {
+        return Err(format!("'rustc --print sysroot' failed:\n{}", String::from_utf8_lossy(&output.stderr)).into());
+    }
+
+    Ok(PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+/// Returns the directory of the rust sources of 'component' by the manifest
+/// rustup writes for every installed component into 'lib/rustlib' of the sysroot,
+/// which lists the files and directories of the component.
+fn component_source_dir(sysroot: &Path, component: &str) -> RtResult<PathBuf> {
+    let rustlib_dir = sysroot.join("lib").join("rustlib");
+    let manifest = find_manifest(&rustlib_dir, component)?
+        .ok_or(format!("The toolchain component '{}' isn't installed in '{}'! Install it with 'rustup component add {}'.",
+                       component, sysroot.display(), component))?;
+
+    let mut src_paths = Vec::new();
+    for line in fs::read_to_string(&manifest)?.lines() {
+        if let Some(file) = line.strip_prefix("file:") {
+            if file.ends_with(".rs") {
+                if let Some(dir) = sysroot.join(file).parent() {
+                    src_paths.push(dir.to_path_buf());
+                }
+            }
+        } else if let Some(dir) = line.strip_prefix("dir:") {
+            let dir = sysroot.join(dir);
+            if dir.is_dir() && ! rust_files(&dir, &[])?.is_empty() {
+                src_paths.push(dir);
+            }
+        }
+    }
+
+    common_dir(&src_paths)
+        .ok_or(format!("The toolchain component '{}' doesn't contain any rust sources!", component).into())
+}
+
+/// Finds the manifest 'manifest-<component>' or 'manifest-<component>-<target>'
+/// of 'component', where '<target>' is an installed target like 'x86_64-unknown-linux-gnu'.
+fn find_manifest(rustlib_dir: &Path, component: &str) -> RtResult<Option<PathBuf>> {
+    let manifest = rustlib_dir.join(format!("manifest-{}", component));
+    if manifest.is_file() {
+        return Ok(Some(manifest));
+    }
+
+    let prefix = format!("manifest-{}-", component);
+    for entry in fs::read_dir(rustlib_dir)? {
+        let path = entry?.path();
+        let target = match path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_prefix(&prefix)) {
+            Some(target) => target.to_string(),
+            None => continue
+        };
+
+        // the sources of 'rust-src' are in 'lib/rustlib/src', which isn't a target
+        if path.is_file() && target.contains('-') && rustlib_dir.join(target).is_dir() {
+            return Ok(Some(path));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The deepest directory containing all of 'paths'.
+fn common_dir(paths: &[PathBuf]) -> Option<PathBuf> {
+    let mut common = paths.first()?.clone();
+    for path in &paths[1..] {
+        while ! path.starts_with(&common) {
+            if ! common.pop() {
+                return None;
+            }
+        }
+    }
+
+    Some(common)
+}
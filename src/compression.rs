@@ -0,0 +1,105 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use tempfile::NamedTempFile;
+
+use rt_result::RtResult;
+use types::CacheCompression;
+use config::Config;
+use tags::move_tags;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Detects the compression of 'file' by its first bytes, so that reading
+/// the cached tags doesn't depend on the current '--cache-compression'.
+pub fn detect_compression(file: &Path) -> RtResult<CacheCompression> {
+    let mut magic = [0; 4];
+    let mut num_read = 0;
+    let mut file = File::open(file)?;
+    while num_read < magic.len() {
+        match file.read(&mut magic[num_read..])? {
+            0 => break,
+            n => num_read += n
+        }
+    }
+
+    let magic = &magic[..num_read];
+    if magic.starts_with(&GZIP_MAGIC) {
+        Ok(CacheCompression::Gzip)
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        Ok(CacheCompression::Zstd)
+    } else {
+        Ok(CacheCompression::None)
+    }
+}
+
+/// Reads the possibly compressed tags file 'file'.
+pub fn read_tags_file(file: &Path) -> RtResult<String> {
+    let contents = match detect_compression(file)? {
+        CacheCompression::None => fs::read(file)?,
+        compression => {
+            let mut cmd = compression_command(compression);
+            cmd.arg("-dc").arg(file);
+            run(compression, &mut cmd)?
+        }
+    };
+
+    String::from_utf8(contents)
+        .map_err(|_| format!("Invalid UTF-8 in tags file '{}'!", file.display()).into())
+}
+
+/// Moves the uncompressed tags file 'from_tags' to 'to_tags', which
+/// gets compressed with 'compression'.
+pub fn move_compressed(config: &Config, compression: CacheCompression, from_tags: &Path, to_tags: &Path) -> RtResult<()> {
+    if compression == CacheCompression::None {
+        return move_tags(config, from_tags, to_tags);
+    }
+
+    verbose!(config, "\nCompress tags with {:?} ...\n   from:\n      {}\n   to:\n      {}",
+             compression, from_tags.display(), to_tags.display());
+
+    let to_dir = to_tags.parent().unwrap_or(Path::new("."));
+    let tmp_tags = NamedTempFile::new_in(to_dir)?;
+    {
+        let mut cmd = compression_command(compression);
+        cmd.arg("-c").arg(from_tags).stdout(Stdio::from(tmp_tags.reopen()?));
+        run(compression, &mut cmd)?;
+    }
+
+    fs::rename(tmp_tags.path(), to_tags)?;
+    fs::remove_file(from_tags)?;
+    Ok(())
+}
+
+fn compression_command(compression: CacheCompression) -> Command {
+    match compression {
+        CacheCompression::Gzip => {
+            let mut cmd = Command::new("gzip");
+            cmd.arg("-n");
+            cmd
+        }
+
+        CacheCompression::Zstd => {
+            let mut cmd = Command::new("zstd");
+            cmd.arg("-q");
+            cmd
+        }
+
+        CacheCompression::None => unreachable!()
+    }
+}
+
+fn run(compression: CacheCompression, cmd: &mut Command) -> RtResult<Vec<u8>> {
+    let exe = format!("{:?}", compression).to_lowercase();
+    let output = cmd.output()
+        .map_err(|err| format!("'{}' execution failed: {}\nIs '{}' correctly installed?", exe, err, exe))?;
+
+    if ! output.status.success() {
+        return Err(format!("'{}' execution failed: {}", exe, String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+
+    Ok(output.stdout)
+}
@@ -1,40 +1,221 @@
 use std::env;
+use std::ffi::OsString;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::fs::File;
 use std::io::Read;
 use std::cmp::max;
 use std::process::Command;
-use clap::App;
+use clap::{App, AppSettings, Arg, SubCommand};
 use toml;
 use num_cpus;
-use types::{TagsExe, TagsKind, TagsSpec};
+use semver::Version;
+use types::{TagsExe, TagsKind, TagsSpec, PseudoTags, SourceVersion, WarningFormat, CacheCompression, LineEnding, TagsFormat, ModuleFilter, MaxFailures};
 use rt_result::RtResult;
+use regex::Regex;
+use cfg::CfgTarget;
 use dirs;
 use tempfile::TempDir;
 
+/// the library target kinds of cargo
+const LIB_KINDS: [&str; 5] = ["lib", "rlib", "dylib", "cdylib", "staticlib"];
+
+/// the format versions of the output of 'cargo metadata' that can be parsed
+const SUPPORTED_METADATA_FORMAT_VERSIONS: [u32; 1] = [1];
+
 /// the configuration used to run rusty-tags
 pub struct Config {
     /// the tags that should be created
     pub tags_spec: TagsSpec,
 
+    /// the first line of the '--version' output of the ctags executable
+    pub ctags_version: String,
+
+    /// recreate the cached tags which were created by another ctags version
+    pub reindex_on_ctags_change: bool,
+
     /// start directory for the search of the 'Cargo.toml'
     pub start_dir: PathBuf,
 
+    /// the start directories of additional cargo workspaces, the dependencies shared
+    /// by the workspaces are only created once
+    pub workspace_dirs: Vec<PathBuf>,
+
+    /// also read the metadata of the workspaces of the path dependencies outside
+    /// of the workspaces, to complete the dependency tree by their dependencies
+    pub follow_path_workspaces: bool,
+
     /// output directory for the tags for the standard library
     pub output_dir_std: Option<PathBuf>,
 
+    /// do not generate tags for the prelude modules of the standard library
+    pub omit_std_prelude: bool,
+
+    /// the rustup toolchain components whose rust sources are tagged
+    pub index_components: Vec<String>,
+
     /// do not generate tags for dependencies
     pub omit_deps: bool,
 
     /// forces the recreation of cached tags
     pub force_recreate: bool,
 
+    /// create the missing directories of the tags files
+    pub create_output_dir: bool,
+
+    /// add the 'crate' and 'version' fields of their source to the vi tags
+    pub embed_provenance: bool,
+
+    /// use paths relative to the tags file for the vi tags of the
+    /// workspace members and absolute ones for the dependencies
+    pub mixed_paths: bool,
+
+    /// make the paths of the vi tags relative to the most specific one
+    /// of these directories containing them, other paths stay absolute
+    pub relative_roots: Vec<PathBuf>,
+
+    /// also write the tags of the roots split by the depth of the dependencies
+    /// into the files '<tags file>.0', '<tags file>.1' and '<tags file>.2plus'
+    pub split_by_depth: bool,
+
+    /// only keep the tags of the crate of the module filter, which
+    /// are in the filtered module, in the final vi tags files
+    pub module_filter: Option<ModuleFilter>,
+
+    /// use line numbers instead of search patterns for the vi tags of dependencies
+    pub compact_deps: bool,
+
+    /// the library target kinds, e.g. 'rlib' or 'cdylib', for which tags are created
+    pub lib_kinds: Vec<String>,
+
+    /// only create tags for the workspace member owning this binary
+    /// target and for its dependencies
+    pub bin: Option<String>,
+
+    /// only use the '[workspace] default-members' as roots
+    /// instead of all workspace members
+    pub default_members_only: bool,
+
+    /// which pseudo tags of ctags are kept in the vi tags
+    pub pseudo_tags: PseudoTags,
+
+    /// only recreate the tags of this crate, of all crates
+    /// depending on it and of the workspace members
+    pub impacted_by: Option<String>,
+
+    /// config overrides forwarded with '--config' to 'cargo metadata'
+    pub cargo_configs: Vec<String>,
+
+    /// the format version of the output of 'cargo metadata'
+    pub metadata_format_version: u32,
+
+    /// only create tags for the files changed since this git reference
+    pub since: Option<String>,
+
+    /// write a JSON index of the trait implementations to this file
+    pub emit_impls: Option<PathBuf>,
+
+    /// write a JSON index of the supertraits of the traits to this file
+    pub emit_trait_hierarchy: Option<PathBuf>,
+
+    /// write the metrics of the tags update in the Prometheus text format to this file
+    pub emit_metrics: Option<PathBuf>,
+
+    /// write a Makefile creating the tags to this path
+    /// instead of creating them
+    pub emit_makefile: Option<PathBuf>,
+
+    /// the files and directories matching one of these regexes are
+    /// omitted from the tags creation
+    pub exclude_paths: Vec<Regex>,
+
+    /// write the tags of the test code of the workspace members
+    /// into a separate '<tags file>.tests'
+    pub split_tests: bool,
+
+    /// create tags for the cargo target directories in the source
+    /// directories, which can be overridden per crate
+    pub index_target_dir: bool,
+
+    /// only tag the crate root files of the targets of the workspace members
+    /// and their module files instead of their whole source directories
+    pub index_target_files_only: bool,
+
+    /// fail if the tags of a workspace member don't contain any of its own tags
+    pub require_member_tags: bool,
+
+    /// command and arguments validating the new tags of the workspace
+    /// members, which are only written if it succeeds
+    pub validate_command: Vec<String>,
+
+    /// skip the files and modules gated by cfg attributes
+    /// which aren't active for this target
+    pub active_cfg_target: Option<CfgTarget>,
+
+    /// skip the rust source files bigger than this number of bytes,
+    /// e.g. the huge generated code of bindgen
+    pub max_file_size: Option<u64>,
+
+    /// the line ending of the vi tags files
+    pub line_ending: LineEnding,
+
+    /// the format of the vi tags files, the minimal one
+    /// only contains the names and the paths of the tags
+    pub tags_format: TagsFormat,
+
+    /// continue with the other sources if the tags creation of a source
+    /// fails and report all failures at the end
+    pub continue_on_error: bool,
+
+    /// the failures of '--continue-on-error' up to which the run still succeeds,
+    /// the tags of the other sources are written in any case
+    pub max_failures: Option<MaxFailures>,
+
+    /// verify the registry sources against the checksums of 'Cargo.lock'
+    /// and warn about mismatches before indexing them
+    pub verify_sources: bool,
+
     /// verbose output about all operations
     pub verbose: bool,
 
     /// don't output anything but errors
     pub quiet: bool,
 
+    /// show the number of processed sources and the sources
+    /// in progress during the tags creation
+    pub progress: bool,
+
+    /// how warnings are formatted
+    pub warning_format: WarningFormat,
+
+    /// after the tags update, update the tags of the source
+    /// files reported as changed on stdin
+    pub serve_stdin: bool,
+
+    /// after the tags update, read the JSON output of 'cargo build' from stdin
+    /// and update the tags of the workspace members after their compilation
+    pub cargo_messages: bool,
+
+    /// list the entries of the cache instead of creating tags
+    pub cache_list: bool,
+
+    /// the source directories of crates set by '$RUSTY_TAGS_SOURCE_OVERRIDE',
+    /// which are used instead of the ones from 'cargo metadata'
+    source_overrides: Vec<SourceOverride>,
+
+    /// the settings of single crates from the '[crates.<name>]'
+    /// tables of the config file
+    crate_configs: HashMap<String, CrateConfig>,
+
+    /// the directory for the cached tags
+    pub cache_dir: PathBuf,
+
+    /// if the cache directory in the temporary directory of the system is used
+    pub local_cache: bool,
+
+    /// how the cached tags are compressed
+    pub cache_compression: CacheCompression,
+
     /// num threads used for the tags creation
     pub num_threads: u32,
 
@@ -44,21 +225,103 @@ pub struct Config {
 
 impl Config {
    pub fn from_command_args() -> RtResult<Config> {
+       Config::from_args(env::args_os())
+   }
+
+   /// The configuration of the arguments 'args' of the 'rusty-tags' command line, including
+   /// the name of the executable as first argument. Exits for invalid arguments, like
+   /// 'from_command_args' does.
+   pub fn from_args<I, T>(args: I) -> RtResult<Config>
+       where I: IntoIterator<Item = T>,
+             T: Into<OsString> + Clone
+   {
        let matches = App::new("rusty-tags")
            .about("Create ctags/etags for a cargo project and all of its dependencies")
            // Pull version from Cargo.toml
            .version(crate_version!())
            .author("Daniel Trstenjak <daniel.trstenjak@gmail.com>")
+           .setting(AppSettings::SubcommandsNegateReqs)
+           .subcommand(SubCommand::with_name("cache-list")
+                .about("List the cached tags with their crate, version, size, time since the last access and if they're used by the current cargo project"))
            .arg_from_usage("<TAGS_KIND> 'The kind of the created tags (vi, emacs)'")
            .arg_from_usage("-s --start-dir [DIR] 'Start directory for the search of the Cargo.toml (default: current working directory)'")
+           .arg(Arg::from_usage("-w --workspace [DIR]... 'Start directory of an additional cargo workspace, can be given multiple times, the dependencies shared by the workspaces are only created once'")
+                .number_of_values(1))
+           .arg_from_usage("--follow-path-workspaces 'Also read the metadata of the cargo workspaces of the path dependencies outside of the workspaces, to add the dependencies missing in the resolve of the workspaces'")
            .arg_from_usage("--output-dir-std [DIR] 'Set the output directory for the tags for the Rust standard library (default: $RUST_SRC_PATH)'")
+           .arg_from_usage("--omit-std-prelude 'Do not create tags for the prelude modules of the Rust standard library, which only reexport the items of other modules, takes effect with the next recreation of the standard library tags'")
+           .arg(Arg::from_usage("--index-component [NAME]... 'Create tags for the rust sources of the installed rustup toolchain component NAME, e.g. \"rustc-dev\" for the compiler sources, written into its source directory, can be given multiple times'")
+                .number_of_values(1))
            .arg_from_usage("-o --omit-deps 'Do not generate tags for dependencies'")
-           .arg_from_usage("-f --force-recreate 'Forces the recreation of the tags of all dependencies and the Rust standard library'")
+           .arg_from_usage("--reindex-on-ctags-change 'Recreate the cached tags of the dependencies which were created by another ctags version, e.g. after an upgrade of ctags, the cached tags without a recorded ctags version are also recreated'")
+           .arg(Arg::from_usage("-f --force-recreate 'Forces the recreation of the tags of all dependencies and the Rust standard library, ignoring but overwriting all cached tags'")
+                .visible_alias("force"))
            .arg_from_usage("-v --verbose 'Verbose output about all operations'")
            .arg_from_usage("-q --quiet 'Don't output anything but errors'")
+           .arg(Arg::from_usage("--progress 'Show the number of sources done and the crates in progress during the tags creation, updated in place on a terminal, otherwise as periodic summary lines on stderr'")
+                .conflicts_with("verbose"))
+           .arg(Arg::from_usage("--warning-format [FORMAT] 'Format of the warnings, quickfix formats them as \"path:line: message\" or \"crate: message\" for the parsing by editors (default: text)'")
+                .possible_values(&WarningFormat::variants())
+                .case_insensitive(true))
            .arg_from_usage("-n --num-threads [NUM] 'Num threads used for the tags creation (default: num available physical cpus)'")
            .arg_from_usage("-O --output [FILENAME] 'Name of output tags file.'")
-           .get_matches();
+           .arg_from_usage("--create-output-dir 'Create the missing directories of the tags files, e.g. for --output=\"tags/rusty-tags.vi\"'")
+           .arg_from_usage("--embed-provenance 'Add the fields \"crate:<name>\" and \"version:<version>\" of their crate to the vi tags'")
+           .arg_from_usage("--mixed-paths 'Use paths relative to the tags file for the vi tags of the workspace members, which keeps the tags file portable within the repository, and absolute paths for the tags of the dependencies'")
+           .arg(Arg::from_usage("--relative-roots [DIR]... 'Make the paths of the vi tags relative to the most specific one of the directories DIR containing them, the other paths stay absolute, e.g. for a tags file shared by workspace members in different top-level directories, can be given multiple times'")
+                .number_of_values(1)
+                .conflicts_with("mixed-paths"))
+           .arg_from_usage("--split-by-depth 'Also write the tags of every workspace member split by the depth of the dependencies beside of its tags file: its own tags into \"<tags file>.0\", the tags of its direct dependencies into \"<tags file>.1\" and of its indirect dependencies into \"<tags file>.2plus\", so that editors can load the deep dependencies lazily'")
+           .arg_from_usage("--module-filter [CRATE::PATH] 'Only keep the tags of the crate CRATE in the module PATH and its submodules in the vi tags files, e.g. \"tokio::net\", the module of a tag is given by its file and its module scope field, the cached tags are kept complete'")
+           .arg_from_usage("--compact-deps 'Use line numbers instead of search patterns for the vi tags of dependencies, whose sources do not change, the workspace members keep the patterns'")
+           .arg_from_usage("--lib-kinds [KINDS] 'Comma separated library target kinds for which tags are created (default: lib,rlib,dylib,cdylib,staticlib)'")
+           .arg_from_usage("--bin [NAME] 'Only create tags for the workspace member owning the binary target NAME and for its dependencies'")
+           .arg(Arg::from_usage("--default-members-only 'Only create tags for the default-members of the workspace, which a bare \"cargo build\" compiles, and for their dependencies'")
+                .conflicts_with("bin"))
+           .arg_from_usage("--ctags-wrapper [CMD] 'Run ctags under the command CMD, e.g. \"strace -f\", which is split into its arguments like by a shell and gets the ctags executable and its arguments appended'")
+           .arg_from_usage("--mark-reexports 'Tag the names of public reexports with the kind \"reexport\", so that editors can prefer the real definitions'")
+           .arg_from_usage("--ctags-fields [SPEC] 'Pass SPEC to the option --fields of ctags to select the fields of the tags, e.g. \"+nS\" or \"+{signature}-{access}\", where long field names in braces need universal ctags, some fields are only written by ctags for its JSON output, the cached tags have to be recreated by --force-recreate after a change'")
+           .arg(Arg::from_usage("--pseudo-tags [MODE] 'Which pseudo tags of ctags are kept in vi tags: all of them, none of them or only the format and sorted ones with rusty-tags as program name, the merged tags get the format and sorted ones unless stripped (default: keep)'")
+                .possible_values(&PseudoTags::variants())
+                .case_insensitive(true))
+           .arg_from_usage("--impacted-by [CRATE] 'Only recreate the tags of CRATE, of all crates depending on it and of the workspace members'")
+           .arg(Arg::from_usage("--cargo-config [KEY=VALUE]... 'Config override forwarded with --config to cargo metadata, can be given multiple times'")
+                .number_of_values(1))
+           .arg_from_usage("--metadata-format-version [N] 'The format version of the output of cargo metadata, only 1 is supported (default: 1)'")
+           .arg_from_usage("--since [GITREF] 'Only create tags for the files of the workspace members changed since GITREF, written to <tags file>.since in the workspace root'")
+           .arg_from_usage("--serve-stdin 'After the tags update, read \"changed <PATH>\" lines from stdin, update the tags of the changed source file and reply \"done <PATH>\", best used with --quiet'")
+           .arg(Arg::from_usage("--cache-compression [COMPRESSION] 'Compress the cached vi tags with gzip or zstd, which have to be installed (default: none)'")
+                .possible_values(&CacheCompression::variants())
+                .case_insensitive(true))
+           .arg(Arg::from_usage("--cargo-messages 'After the tags update, read the output of \"cargo build --message-format=json\" from stdin and update the tags of the workspace members after their compilation'")
+                .conflicts_with("serve-stdin"))
+           .arg_from_usage("--local-cache 'Use a cache directory in $XDG_RUNTIME_DIR or in the temporary directory of the system, e.g. if the home directory is on a network file system'")
+           .arg_from_usage("--emit-impls [PATH] 'Write a JSON index mapping each trait to the locations of its implementations'")
+           .arg_from_usage("--emit-trait-hierarchy [PATH] 'Write a JSON index mapping each trait to its definitions with their supertraits'")
+           .arg_from_usage("--emit-metrics [PATH] 'Write the metrics of the tags update - the number of sources, the cache hit ratio, the number of tags, the duration and the failures - in the Prometheus text format to PATH, e.g. for the textfile collector of the node exporter'")
+           .arg_from_usage("--emit-makefile [PATH] 'Write a Makefile to PATH with a target for the cached tags of every source and for the tags of the workspace members instead of creating the tags, run e.g. by \"make -j -f PATH\"'")
+           .arg(Arg::from_usage("--exclude-path [REGEX]... 'Omit the source files and directories whose path matches REGEX from the tags creation, directory paths end with a slash, e.g. \"/generated/\", can be given multiple times'")
+                .number_of_values(1))
+           .arg_from_usage("--split-tests 'Write the tags of the test targets and test modules of the workspace members into a separate <tags file>.tests'")
+           .arg_from_usage("--max-file-size [BYTES] 'Skip the rust source files of the crates which are bigger than BYTES, e.g. huge generated files, the skipped files are listed with --verbose'")
+           .arg_from_usage("--index-target-dir 'Create tags for the cargo target directories in the source directories, e.g. for the code generated by build scripts, which can be overridden by the index_target_dir setting of a crate'")
+           .arg_from_usage("--index-target-files-only 'Only tag the crate root files of the targets of the workspace members and the files of the modules declared in them, found best-effort by their mod declarations, instead of their whole source directories'")
+           .arg_from_usage("--require-member-tags 'Exit with an error listing the workspace members without any tags of their own after the tags creation, e.g. because of a wrongly resolved source path'")
+           .arg_from_usage("--validate-command [CMD] 'Run the command CMD, e.g. \"grep -q MySymbol\", which is split into its arguments like by a shell, for the new tags of each workspace member before they replace the previous ones, it gets the path of the new tags file appended, its contents on stdin and the path of the replaced tags file in RUSTY_TAGS_FINAL_FILE, if it fails, then the previous tags are kept and rusty-tags exits with an error'")
+           .arg_from_usage("--active-cfg-only 'Skip the files and modules which are gated by a cfg attribute like #[cfg(target_os = \"windows\")] for another target than the host or the one of --cfg-target'")
+           .arg(Arg::from_usage("--cfg-target [TRIPLE] 'The target triple, e.g. \"x86_64-pc-windows-msvc\", for which the cfg attributes are evaluated by --active-cfg-only'")
+                .requires("active-cfg-only"))
+           .arg(Arg::from_usage("--line-ending [LINE_ENDING] 'Line ending of the written vi tags files, native is crlf on windows and lf otherwise (default: lf)'")
+                .possible_values(&LineEnding::variants())
+                .case_insensitive(true))
+           .arg(Arg::from_usage("--format [FORMAT] 'Format of the written vi tags files, minimal only keeps the name and the path of every tag, which is the smallest index still supporting the jump to the file of a tag (default: full)'")
+                .possible_values(&TagsFormat::variants())
+                .case_insensitive(true))
+           .arg_from_usage("--continue-on-error 'Continue with the other sources if the tags creation of a source fails, report all failures as JSON at the end and exit with an error'")
+           .arg(Arg::from_usage("--max-failures [N_OR_PCT] 'Only exit with an error for --continue-on-error if more than N sources or PCT percent of the updated sources failed, e.g. \"3\" or \"10%\", the failures are still reported'")
+                .requires("continue-on-error"))
+           .arg_from_usage("--verify-sources 'Verify the registry sources before indexing them: the extraction marker \".cargo-ok\" has to exist and the cached crate archive has to match the checksum of Cargo.lock, mismatches are printed as warnings'")
+           .get_matches_from(args);
 
        let start_dir = matches.value_of("start-dir")
            .map(PathBuf::from)
@@ -68,6 +331,17 @@ impl Config {
            return Err(format!("Invalid directory given to '--start-dir': '{}'!", start_dir.display()).into());
        }
 
+       let workspace_dirs: Vec<PathBuf> = matches.values_of("workspace")
+           .map(|vs| vs.map(PathBuf::from).collect())
+           .unwrap_or_default();
+
+       for workspace_dir in &workspace_dirs {
+           if ! workspace_dir.is_dir() {
+               return Err(format!("Invalid directory given to '--workspace': '{}'!", workspace_dir.display()).into());
+           }
+       }
+
+       let follow_path_workspaces = matches.is_present("follow-path-workspaces");
        let output_dir_std = matches.value_of("output-dir-std").map(PathBuf::from);
 
        if let Some(ref output_dir_std) = output_dir_std {
@@ -76,13 +350,19 @@ impl Config {
            }
        }
 
-       let kind = value_t_or_exit!(matches.value_of("TAGS_KIND"), TagsKind);
+       let cache_list = matches.subcommand_matches("cache-list").is_some();
+       let kind = if cache_list {
+           TagsKind::Vi
+       } else {
+           value_t_or_exit!(matches.value_of("TAGS_KIND"), TagsKind)
+       };
 
-       let (vi_tags, emacs_tags, ctags_exe, ctags_options) = {
+       let (vi_tags, emacs_tags, ctags_exe, ctags_options, crate_configs) = {
            let mut vt = "rusty-tags.vi".to_string();
            let mut et = "rusty-tags.emacs".to_string();
            let mut cte = None;
            let mut cto = "".to_string();
+           let mut ccs = HashMap::new();
 
            // Override defaults with file config
            if let Some(file_config) = ConfigFromFile::load()? {
@@ -90,6 +370,7 @@ impl Config {
                if let Some(fcet) = file_config.emacs_tags { et = fcet; }
                cte = file_config.ctags_exe;
                if let Some(fccto) = file_config.ctags_options { cto = fccto; }
+               if let Some(fcccs) = file_config.crates { ccs = fcccs; }
            }
 
            // Override defaults with commandline options
@@ -100,13 +381,154 @@ impl Config {
                }
            }
 
-           (vt, et, cte, cto)
+           (vt, et, cte, cto, ccs)
        };
 
+       let mark_reexports = matches.is_present("mark-reexports");
+       let ctags_fields = matches.value_of("ctags-fields").map(String::from);
+       let ctags_wrapper = match matches.value_of("ctags-wrapper") {
+           Some(cmd) => shell_words::split(cmd)
+               .map_err(|err| format!("Invalid command '{}' given to '--ctags-wrapper': {}", cmd, err))?,
+           None => Vec::new()
+       };
+
+       let omit_std_prelude = matches.is_present("omit-std-prelude");
+       let index_components: Vec<String> = matches.values_of("index-component")
+           .map(|vs| vs.map(String::from).collect())
+           .unwrap_or_default();
+
        let omit_deps = matches.is_present("omit-deps");
        let force_recreate = matches.is_present("force-recreate");
+       let create_output_dir = matches.is_present("create-output-dir");
+       let embed_provenance = matches.is_present("embed-provenance");
+       if embed_provenance && kind == TagsKind::Emacs {
+           return Err("The option '--embed-provenance' is only supported for vi tags, because emacs tags have no fields!".into());
+       }
+
+       let mixed_paths = matches.is_present("mixed-paths");
+       if mixed_paths && kind == TagsKind::Emacs {
+           return Err("The option '--mixed-paths' is only supported for vi tags!".into());
+       }
+
+       let mut relative_roots = Vec::new();
+       for dir in matches.values_of("relative-roots").into_iter().flatten() {
+           match Path::new(dir).canonicalize() {
+               Ok(ref root) if root.is_dir() => relative_roots.push(root.clone()),
+               _ => return Err(format!("Invalid directory given to '--relative-roots': '{}'!", dir).into())
+           }
+       }
+
+       if ! relative_roots.is_empty() && kind == TagsKind::Emacs {
+           return Err("The option '--relative-roots' is only supported for vi tags!".into());
+       }
+
+       let split_by_depth = matches.is_present("split-by-depth");
+       let module_filter = match matches.value_of("module-filter") {
+           Some(filter) => Some(ModuleFilter::parse(filter)?),
+           None => None
+       };
+
+       if module_filter.is_some() && kind == TagsKind::Emacs {
+           return Err("The option '--module-filter' is only supported for vi tags!".into());
+       }
+
+       let compact_deps = matches.is_present("compact-deps");
+       let lib_kinds: Vec<String> = matches.value_of("lib-kinds")
+           .unwrap_or(LIB_KINDS.join(",").as_str())
+           .split(',')
+           .map(|k| k.trim().to_string())
+           .filter(|k| ! k.is_empty())
+           .collect();
+
+       if let Some(kind) = lib_kinds.iter().find(|k| ! LIB_KINDS.contains(&k.as_str())) {
+           return Err(format!("Invalid library kind given to '--lib-kinds': '{}'! Supported kinds: {}", kind, LIB_KINDS.join(", ")).into());
+       }
+
+       let bin = matches.value_of("bin").map(String::from);
+       let default_members_only = matches.is_present("default-members-only");
+       let impacted_by = matches.value_of("impacted-by").map(String::from);
+       let pseudo_tags = value_t!(matches.value_of("pseudo-tags"), PseudoTags).unwrap_or(PseudoTags::Keep);
+
+       let cargo_configs: Vec<String> = matches.values_of("cargo-config")
+           .map(|vs| vs.map(String::from).collect())
+           .unwrap_or_default();
+
+       for cargo_config in &cargo_configs {
+           validate_cargo_config(cargo_config)?;
+       }
+
+       let metadata_format_version = match matches.value_of("metadata-format-version") {
+           Some(version) => parse_metadata_format_version(version)?,
+           None => 1
+       };
+
+       let max_file_size = match matches.value_of("max-file-size") {
+           Some(size) => Some(size.parse::<u64>()
+               .map_err(|_| format!("Invalid maximal file size '{}', expected a number of bytes like 1000000!", size))?),
+           None => None
+       };
+
+       let since = matches.value_of("since").map(String::from);
+       let emit_impls = matches.value_of("emit-impls").map(PathBuf::from);
+       let emit_trait_hierarchy = matches.value_of("emit-trait-hierarchy").map(PathBuf::from);
+       let emit_metrics = matches.value_of("emit-metrics").map(PathBuf::from);
+       let emit_makefile = matches.value_of("emit-makefile").map(PathBuf::from);
+       let split_tests = matches.is_present("split-tests");
+       let index_target_dir = matches.is_present("index-target-dir");
+       let index_target_files_only = matches.is_present("index-target-files-only");
+       let require_member_tags = matches.is_present("require-member-tags");
+       let validate_command = match matches.value_of("validate-command") {
+           Some(cmd) => shell_words::split(cmd)
+               .map_err(|err| format!("Invalid command '{}' given to '--validate-command': {}", cmd, err))?,
+           None => Vec::new()
+       };
+       let active_cfg_target = match (matches.is_present("active-cfg-only"), matches.value_of("cfg-target")) {
+           (true, Some(triple)) => Some(CfgTarget::from_triple(triple)?),
+           (true, None) => Some(CfgTarget::host()),
+           (false, _) => None
+       };
+
+       let line_ending = value_t!(matches.value_of("line-ending"), LineEnding).unwrap_or(LineEnding::Lf);
+       if line_ending != LineEnding::Lf && kind == TagsKind::Emacs {
+           return Err("The option '--line-ending' is only supported for vi tags, because emacs tags contain byte offsets!".into());
+       }
+
+       let tags_format = value_t!(matches.value_of("format"), TagsFormat).unwrap_or(TagsFormat::Full);
+       if tags_format != TagsFormat::Full && kind == TagsKind::Emacs {
+           return Err("The option '--format' is only supported for vi tags!".into());
+       }
+
+       let continue_on_error = matches.is_present("continue-on-error");
+       let max_failures = match matches.value_of("max-failures") {
+           Some(max) => Some(MaxFailures::parse(max)?),
+           None => None
+       };
+
+       let verify_sources = matches.is_present("verify-sources");
+       let exclude_paths = match matches.values_of("exclude-path") {
+           Some(vs) => vs.map(|p| Regex::new(p).map_err(|err| format!("Invalid regex '{}' given to '--exclude-path': {}", p, err)))
+                         .collect::<Result<Vec<_>, _>>()?,
+           None => Vec::new()
+       };
+
+       let serve_stdin = matches.is_present("serve-stdin");
+       let cargo_messages = matches.is_present("cargo-messages");
+       let source_overrides = SourceOverride::from_env()?;
+       let local_cache = matches.is_present("local-cache");
+       let cache_dir = if local_cache {
+           dirs::rusty_tags_local_cache_dir()?
+       } else {
+           dirs::rusty_tags_cache_dir()?.to_path_buf()
+       };
+       let cache_compression = value_t!(matches.value_of("cache-compression"), CacheCompression).unwrap_or(CacheCompression::None);
+       if cache_compression != CacheCompression::None && kind == TagsKind::Emacs {
+           return Err("The option '--cache-compression' is only supported for vi tags, because emacs includes the cached tags!".into());
+       }
+
        let quiet = matches.is_present("quiet");
+       let warning_format = value_t!(matches.value_of("warning-format"), WarningFormat).unwrap_or(WarningFormat::Text);
        let verbose = if quiet { false } else { matches.is_present("verbose") };
+       let progress = ! quiet && matches.is_present("progress");
 
        let num_threads = if verbose {
            println!("Switching to single threaded for verbose output");
@@ -122,24 +544,99 @@ impl Config {
                     vi_tags, emacs_tags, ctags_exe, ctags_options);
        }
 
-       let ctags_exe = detect_tags_exe(&ctags_exe)?;
+       let (ctags_exe, ctags_version) = detect_tags_exe(&ctags_exe)?;
        if verbose {
-           println!("Found ctags executable: {:?}", ctags_exe);
+           println!("Found ctags executable: {:?}, version: '{}'", ctags_exe, ctags_version);
+       }
+
+       let reindex_on_ctags_change = matches.is_present("reindex-on-ctags-change");
+       let mut tags_spec = TagsSpec::new(kind, ctags_exe, vi_tags, emacs_tags, ctags_options, mark_reexports, ctags_wrapper)?;
+       if let Some(fields) = ctags_fields {
+           tags_spec.set_ctags_fields(fields)?;
        }
 
        Ok(Config {
-           tags_spec: TagsSpec::new(kind, ctags_exe, vi_tags, emacs_tags, ctags_options)?,
+           tags_spec,
+           ctags_version,
+           reindex_on_ctags_change,
            start_dir: start_dir,
+           workspace_dirs,
+           follow_path_workspaces,
            output_dir_std: output_dir_std,
+           omit_std_prelude,
+           index_components,
            omit_deps: omit_deps,
            force_recreate: force_recreate,
+           create_output_dir,
+           embed_provenance,
+           mixed_paths,
+           relative_roots,
+           split_by_depth,
+           module_filter,
+           compact_deps,
+           lib_kinds,
+           bin,
+           default_members_only,
+           pseudo_tags,
+           impacted_by,
+           cargo_configs,
+           metadata_format_version,
+           since,
+           emit_impls,
+           emit_trait_hierarchy,
+           emit_metrics,
+           emit_makefile,
+           exclude_paths,
+           split_tests,
+           index_target_dir,
+           index_target_files_only,
+           require_member_tags,
+           validate_command,
+           active_cfg_target,
+           max_file_size,
+           line_ending,
+           tags_format,
+           continue_on_error,
+           max_failures,
+           verify_sources,
            verbose: verbose,
            quiet: quiet,
+           progress,
+           warning_format,
+           serve_stdin,
+           cargo_messages,
+           cache_list,
+           source_overrides,
+           crate_configs,
+           cache_dir,
+           local_cache,
+           cache_compression,
            num_threads: num_threads,
            temp_dir: TempDir::new()?
        })
    }
 
+   /// the source directory set by '$RUSTY_TAGS_SOURCE_OVERRIDE' for 'source_version'
+   pub fn source_override(&self, source_version: &SourceVersion) -> Option<&Path> {
+       self.source_overrides
+           .iter()
+           .find(|o| o.name == source_version.name && o.version == source_version.version)
+           .map(|o| o.path.as_path())
+   }
+
+   /// the settings of the crate 'crate_name' from the config file
+   pub fn crate_config(&self, crate_name: &str) -> Option<&CrateConfig> {
+       self.crate_configs.get(crate_name)
+   }
+
+   /// if the cargo target directories in the source directory of the crate 'crate_name'
+   /// are tagged, by its 'index_target_dir' setting or else by '--index-target-dir'
+   pub fn index_target_dir(&self, crate_name: &str) -> bool {
+       self.crate_config(crate_name)
+           .and_then(|c| c.index_target_dir)
+           .unwrap_or(self.index_target_dir)
+   }
+
    pub fn temp_file(&self, name: &str) -> RtResult<PathBuf> {
        let file_path = self.temp_dir.path().join(name);
        let _ = File::create(&file_path)?;
@@ -147,6 +644,66 @@ impl Config {
    }
 }
 
+/// A source directory override of a crate by '$RUSTY_TAGS_SOURCE_OVERRIDE'.
+struct SourceOverride {
+    name: String,
+    version: Version,
+    path: PathBuf
+}
+
+impl SourceOverride {
+    /// Reads the overrides from '$RUSTY_TAGS_SOURCE_OVERRIDE', which contains
+    /// 'name@version=PATH' entries separated like the entries of '$PATH',
+    /// or the path to a file with one such entry per line.
+    fn from_env() -> RtResult<Vec<SourceOverride>> {
+        let value = match env::var_os("RUSTY_TAGS_SOURCE_OVERRIDE") {
+            Some(value) => value,
+            None        => return Ok(Vec::new())
+        };
+
+        let entries: Vec<String> = if Path::new(&value).is_file() {
+            map_file(Path::new(&value), |contents| {
+                Ok(contents.lines()
+                   .map(str::trim)
+                   .filter(|l| ! l.is_empty() && ! l.starts_with('#'))
+                   .map(String::from)
+                   .collect())
+            })?
+        } else {
+            env::split_paths(&value)
+                .map(|p| p.to_string_lossy().into_owned())
+                .filter(|e| ! e.is_empty())
+                .collect()
+        };
+
+        entries.iter().map(|e| SourceOverride::parse(e)).collect()
+    }
+
+    fn parse(entry: &str) -> RtResult<SourceOverride> {
+        let invalid = || format!("Invalid entry '{}' in $RUSTY_TAGS_SOURCE_OVERRIDE! Expected 'name@version=PATH'.", entry);
+        let (crate_version, path) = {
+            let mut split = entry.splitn(2, '=');
+            (split.next().ok_or_else(invalid)?, split.next().ok_or_else(invalid)?)
+        };
+
+        let (name, version) = {
+            let mut split = crate_version.splitn(2, '@');
+            (split.next().ok_or_else(invalid)?, split.next().ok_or_else(invalid)?)
+        };
+
+        let path = PathBuf::from(path);
+        if ! path.is_dir() {
+            return Err(format!("Invalid directory '{}' in $RUSTY_TAGS_SOURCE_OVERRIDE for '{}'!", path.display(), crate_version).into());
+        }
+
+        Ok(SourceOverride {
+            name: name.trim().to_string(),
+            version: Version::parse(version.trim())?,
+            path
+        })
+    }
+}
+
 /// Represents the data from a `.rusty-tags/config.toml` configuration file.
 #[derive(Deserialize, Debug, Default)]
 struct ConfigFromFile {
@@ -160,7 +717,36 @@ struct ConfigFromFile {
     ctags_exe: Option<String>,
 
     /// options given to the ctags executable
-    ctags_options: Option<String>
+    ctags_options: Option<String>,
+
+    /// the settings of single crates by their name
+    crates: Option<HashMap<String, CrateConfig>>
+}
+
+/// The settings of a single crate in the config file, e.g.:
+///
+///    [crates.serde]
+///    doc_tests = true
+///    index_target_dir = false
+///    out_dir_globs = ["**/bindings.rs"]
+///    pre_tag_command = ["make", "generate"]
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct CrateConfig {
+    /// also create tags for the code of the doc-tests in the doc comments of the crate
+    pub doc_tests: bool,
+
+    /// create tags for the cargo target directories in the source directory of the
+    /// crate, e.g. for the code generated by build scripts, overrides '--index-target-dir'
+    pub index_target_dir: Option<bool>,
+
+    /// glob patterns of the rust source files in the build script output directory
+    /// of the crate ('OUT_DIR'), which are tagged as part of the crate
+    pub out_dir_globs: Vec<String>,
+
+    /// the command and its arguments, which is run in the source directory of the
+    /// crate before its tags are created, e.g. to generate code which then gets tagged
+    pub pre_tag_command: Vec<String>
 }
 
 impl ConfigFromFile {
@@ -193,7 +779,42 @@ fn map_file<R, F>(file: &Path, f: F) -> RtResult<R>
     Ok(r)
 }
 
-fn detect_tags_exe(ctags_exe: &Option<String>) -> RtResult<TagsExe> {
+/// Parses the format version given to '--metadata-format-version', which has to be
+/// one of 'SUPPORTED_METADATA_FORMAT_VERSIONS'.
+fn parse_metadata_format_version(version: &str) -> RtResult<u32> {
+    let version = version.parse::<u32>()
+        .map_err(|_| format!("Invalid metadata format version '{}', expected a number like 1!", version))?;
+
+    if ! SUPPORTED_METADATA_FORMAT_VERSIONS.contains(&version) {
+        return Err(format!("Unsupported metadata format version '{}', supported are: {:?}!",
+                           version, SUPPORTED_METADATA_FORMAT_VERSIONS).into());
+    }
+
+    Ok(version)
+}
+
+/// Ensures that 'cargo_config' is a 'KEY=VALUE' config override, which
+/// can't sneak in other arguments for 'cargo metadata', like a different
+/// '--format-version'.
+fn validate_cargo_config(cargo_config: &str) -> RtResult<()> {
+    let key = match cargo_config.find('=') {
+        Some(idx) => cargo_config[..idx].trim(),
+        None      => ""
+    };
+
+    if key.is_empty() || key.starts_with('-') {
+        return Err(format!("Invalid value given to '--cargo-config': '{}'! Expected 'KEY=VALUE'.", cargo_config).into());
+    }
+
+    if key.split('.').any(|k| k.trim().trim_matches('"') == "format-version") {
+        return Err(format!("It's not supported to change the format version of 'cargo metadata' by '--cargo-config': '{}'!", cargo_config).into());
+    }
+
+    Ok(())
+}
+
+/// Returns the found ctags executable and the first line of its version output.
+fn detect_tags_exe(ctags_exe: &Option<String>) -> RtResult<(TagsExe, String)> {
     let exes = match *ctags_exe {
         Some(ref exe) if exe != "" => vec![exe.as_str()],
         _                          => vec!["ctags", "exuberant-ctags", "exctags", "universal-ctags", "uctags"]
@@ -206,14 +827,47 @@ fn detect_tags_exe(ctags_exe: &Option<String>) -> RtResult<TagsExe> {
         if let Ok(output) = cmd.output() {
             if output.status.success() {
                 let stdout = String::from_utf8_lossy(&output.stdout);
+                let version = stdout.lines().next().unwrap_or("").trim().to_string();
                 if stdout.contains("Universal Ctags") {
-                    return Ok(TagsExe::UniversalCtags(exe.to_string()));
+                    return Ok((TagsExe::UniversalCtags(exe.to_string()), version));
                 }
 
-                return Ok(TagsExe::ExuberantCtags(exe.to_string()));
+                return Ok((TagsExe::ExuberantCtags(exe.to_string()), version));
             }
         }
     }
 
     Err(format!("Couldn't find 'ctags' executable! Searched for executables with names: {:?}. Is 'ctags' correctly installed?", &exes).into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_cargo_config_of_valid_values() {
+        for cargo_config in &["net.offline=true", "build.target-dir=\"/tmp/target\"", "env.X=\"format-version\"",
+                              "registries.format-version-registry.index=\"https://example.com\""] {
+            assert!(validate_cargo_config(cargo_config).is_ok(), "{}", cargo_config);
+        }
+    }
+
+    #[test]
+    fn validate_cargo_config_of_invalid_values() {
+        for cargo_config in &["", "net.offline", "=true", "--offline=true", "format-version=2", "metadata.\"format-version\"=2"] {
+            assert!(validate_cargo_config(cargo_config).is_err(), "{}", cargo_config);
+        }
+    }
+
+    #[test]
+    fn parse_supported_metadata_format_version() {
+        assert_eq!(parse_metadata_format_version("1").unwrap(), 1);
+    }
+
+    #[test]
+    fn parse_unsupported_metadata_format_version() {
+        for version in &["0", "2", "-1", "one", ""] {
+            assert!(parse_metadata_format_version(version).is_err(), "{}", version);
+        }
+    }
+}
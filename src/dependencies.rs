@@ -1,32 +1,109 @@
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use serde_json;
-use fnv::FnvHashMap;
+use toml;
+use fnv::{FnvHashMap, FnvHashSet};
 
 use rt_result::RtResult;
 use types::{DepTree, Source, SourceVersion, SourceId};
 use config::Config;
+use output::Location;
+use verify::{lock_checksums, verify_source};
+use globset::{GlobBuilder, GlobMatcher};
+use files::{build_out_dir, rust_files};
 
 type JsonValue = serde_json::Value;
 type JsonObject = serde_json::Map<String, JsonValue>;
 
-/// Returns the dependency tree of the whole cargo workspace.
-pub fn dependency_tree(config: &Config, metadata: &JsonValue) -> RtResult<DepTree> {
+/// Returns the dependency tree of the whole cargo workspace. If the metadata
+/// of multiple workspaces is given, then one tree containing all of them is built,
+/// where each source - identified by its 'SourceVersion' - is only contained once.
+///
+/// The members of the workspaces of 'path_metadatas' - of '--follow-path-workspaces' -
+/// aren't roots, their sources and dependencies only complete the tree where the
+/// metadatas of the workspaces lack them.
+pub fn dependency_tree(config: &Config, metadatas: &[JsonValue], path_metadatas: &[JsonValue]) -> RtResult<DepTree> {
     let mut dep_tree = DepTree::new();
-    let packages = packages(config, metadata, &mut dep_tree)?;
+    let all_metadatas: Vec<&JsonValue> = metadatas.iter().chain(path_metadatas).collect();
+    let packages = packages(config, &all_metadatas, &mut dep_tree)?;
+
+    build_dep_tree(config, metadatas, &all_metadatas, &packages, &mut dep_tree)?;
+    if config.bin.is_some() || config.default_members_only || ! path_metadatas.is_empty() {
+        dep_tree.retain_reachable_from_roots();
+    }
 
-    build_dep_tree(config, metadata, &packages, &mut dep_tree)?;
     dep_tree.compute_depths();
 
     Ok(dep_tree)
 }
 
+/// Returns the directories of the workspaces - outside of the workspace of 'metadata' - of
+/// the path dependencies in 'metadata', whose workspace is found by the first 'Cargo.toml'
+/// with a '[workspace]' section in the directory of the dependency or in one of its parents.
+pub fn path_workspace_dirs(config: &Config, metadata: &JsonValue) -> RtResult<Vec<PathBuf>> {
+    let workspace_root = Path::new(as_str_from_value("workspace_root", metadata)?);
+    let workspace_members = workspace_members(metadata)?;
+    let mut dirs = Vec::new();
+    for package in as_array_from_value("packages", metadata)? {
+        let source_version = SourceVersion::parse_from_id(as_str_from_value("id", package)?)?;
+        let is_path_dep = package.get("source").is_none_or(JsonValue::is_null);
+        if ! is_path_dep || workspace_members.contains(&source_version) || config.source_override(&source_version).is_some() {
+            continue;
+        }
+
+        let manifest_path = manifest_path(package)?;
+        let package_dir = match manifest_path.parent() {
+            Some(dir) if ! dir.starts_with(workspace_root) => dir,
+            _ => continue
+        };
+
+        let workspace_dir = package_dir.ancestors()
+            .find(|dir| has_workspace_section(&dir.join("Cargo.toml")));
+
+        if let Some(workspace_dir) = workspace_dir {
+            if ! workspace_dir.starts_with(workspace_root) && ! dirs.iter().any(|d| d == workspace_dir) {
+                verbose!(config, "Found workspace of path dependency {} at '{}'", source_version, workspace_dir.display());
+                dirs.push(workspace_dir.to_path_buf());
+            }
+        }
+    }
+
+    Ok(dirs)
+}
+
+fn has_workspace_section(manifest: &Path) -> bool {
+    let contents = match fs::read_to_string(manifest) {
+        Ok(contents) => contents,
+        Err(_) => return false
+    };
+
+    toml::from_str::<toml::Value>(&contents)
+        .map(|toml| toml.get("workspace").is_some())
+        .unwrap_or(false)
+}
+
 fn workspace_members(metadata: &JsonValue) -> RtResult<Vec<SourceVersion>> {
-    let members = as_array_from_value("workspace_members", metadata)?;
+    source_versions_of("workspace_members", metadata)
+}
+
+/// Returns the members of the '[workspace] default-members', which are all members
+/// if the workspace doesn't define them. Only newer cargo versions (1.71+) provide
+/// them in the metadata.
+fn workspace_default_members<'a>(metadata: &'a JsonValue) -> RtResult<Vec<SourceVersion<'a>>> {
+    if metadata.get("workspace_default_members").is_none() {
+        return Err("Missing 'workspace_default_members' in the metadata, '--default-members-only' requires cargo 1.71 or newer!".into());
+    }
+
+    source_versions_of("workspace_default_members", metadata)
+}
+
+fn source_versions_of<'a>(key: &str, metadata: &'a JsonValue) -> RtResult<Vec<SourceVersion<'a>>> {
+    let members = as_array_from_value(key, metadata)?;
     let mut source_versions = Vec::with_capacity(members.len());
     for member in members {
         let member_str = member.as_str()
-            .ok_or(format!("Expected 'workspace_members' of type string but found: {}", to_string_pretty(member)))?;
+            .ok_or(format!("Expected '{}' of type string but found: {}", key, to_string_pretty(member)))?;
 
         source_versions.push(SourceVersion::parse_from_id(member_str)?)
     }
@@ -34,59 +111,213 @@ fn workspace_members(metadata: &JsonValue) -> RtResult<Vec<SourceVersion>> {
     Ok(source_versions)
 }
 
-struct Package<'a> {
+/// Returns the workspace member owning the binary target with the name 'bin_name'.
+fn bin_owner<'a>(bin_name: &str, metadatas: &'a [JsonValue]) -> RtResult<SourceVersion<'a>> {
+    for metadata in metadatas {
+        if let Some(owner) = bin_owner_in_workspace(bin_name, metadata)? {
+            return Ok(owner);
+        }
+    }
+
+    Err(format!("Couldn't find a workspace member with the binary target '{}'!", bin_name).into())
+}
+
+fn bin_owner_in_workspace<'a>(bin_name: &str, metadata: &'a JsonValue) -> RtResult<Option<SourceVersion<'a>>> {
+    let workspace_members = workspace_members(metadata)?;
+    let packages = as_array_from_value("packages", metadata)?;
+    for package in packages {
+        let source_version = SourceVersion::parse_from_id(as_str_from_value("id", package)?)?;
+        if ! workspace_members.contains(&source_version) {
+            continue;
+        }
+
+        for target in as_array_from_value("targets", package)? {
+            if as_str_from_value("name", target)? != bin_name {
+                continue;
+            }
+
+            let kinds = as_array_from_value("kind", target)?;
+            if kinds.iter().any(|k| k.as_str() == Some("bin")) {
+                return Ok(Some(source_version));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+struct Package {
     pub source_id: SourceId,
-    pub source_path: &'a Path
+    pub source_path: PathBuf,
+    pub test_paths: Vec<PathBuf>,
+    pub out_files: Vec<PathBuf>,
+    pub target_files: Vec<PathBuf>,
+
+    /// the 'source' of the package, 'None' for path dependencies and source overrides
+    pub cargo_source: Option<String>
 }
 
-type Packages<'a> = FnvHashMap<SourceVersion<'a>, Package<'a>>;
+type Packages<'a> = FnvHashMap<SourceVersion<'a>, Package>;
 
 fn packages<'a>(config: &Config,
-                metadata: &'a JsonValue,
+                metadatas: &[&'a JsonValue],
                 dep_tree: &mut DepTree)
                 -> RtResult<Packages<'a>> {
-    let packages = as_array_from_value("packages", metadata)?;
+    let mut packages = Vec::new();
+    for metadata in metadatas {
+        packages.extend(as_array_from_value("packages", metadata)?);
+    }
+
+    let checksums = if config.verify_sources {
+        lock_checksums(config, metadatas)?
+    } else {
+        Default::default()
+    };
+
+    // the target directories containing the build script output directories
+    let mut target_dirs: Vec<PathBuf> = metadatas.iter()
+        .filter_map(|m| m.get("target_directory").and_then(JsonValue::as_str).map(PathBuf::from))
+        .collect();
+
+    target_dirs.sort();
+    target_dirs.dedup();
+
     dep_tree.reserve_num_sources(packages.len());
-    let mut package_map = FnvHashMap::default();
+    let mut package_map: Packages = FnvHashMap::default();
+
+    // the distinct source paths of the packages with the same name and version,
+    // e.g. of different sources, of which only the first one gets tagged
+    let mut duplicate_paths: FnvHashMap<SourceVersion, Vec<PathBuf>> = FnvHashMap::default();
+
     for package in packages {
         let id = as_str_from_value("id", package)?;
         let source_version = SourceVersion::parse_from_id(id)?;
 
-        let source_path = {
+        // a package shared by multiple workspaces or another package with the same name and version
+        if let Some(known_package) = package_map.get(&source_version) {
+            if config.source_override(&source_version).is_none() {
+                if let Ok(Some(path)) = source_path(config, package) {
+                    let paths = duplicate_paths.entry(source_version)
+                        .or_insert_with(|| vec![known_package.source_path.clone()]);
+
+                    if ! paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+            }
+
+            continue;
+        }
+
+        let mut cargo_source = package.get("source").and_then(JsonValue::as_str).map(String::from);
+        let source_path = if let Some(path) = config.source_override(&source_version) {
+            cargo_source = None;
+            verbose!(config, "Using source override of {} at '{}'", source_version, path.display());
+            path.to_path_buf()
+        } else {
             let path = source_path(config, package)?;
             if path == None {
                 continue;
             }
 
+            if config.verify_sources {
+                if let Some(manifest_dir) = manifest_path(package)?.parent() {
+                    verify_source(config, package, &source_version, manifest_dir, &checksums)?;
+                }
+            }
+
             path.unwrap()
         };
 
         verbose!(config, "Found package of {} with source at '{}'", source_version, source_path.display());
 
+        let test_paths = if config.split_tests {
+            test_paths(package, &source_path)?
+        } else {
+            Vec::new()
+        };
+
+        let target_files = if config.index_target_files_only {
+            target_files(config, package)?
+        } else {
+            Vec::new()
+        };
+
+        let out_files = out_files(config, &source_version, &target_dirs)?;
+
         let source_id = dep_tree.new_source();
-        package_map.insert(source_version, Package { source_id, source_path });
+        package_map.insert(source_version, Package { source_id, source_path, test_paths, out_files, target_files, cargo_source });
+    }
+
+    let mut duplicate_paths: Vec<_> = duplicate_paths.into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect();
+
+    duplicate_paths.sort();
+    for (source_version, paths) in &duplicate_paths {
+        let paths: Vec<String> = paths.iter().map(|p| format!("'{}'", p.display())).collect();
+        warning!(config, Location::Crate(source_version.name),
+                 "Found {} source paths of version {}, only the first one is tagged: {}",
+                 paths.len(), source_version.version, paths.join(", "));
     }
 
     Ok(package_map)
 }
 
 fn build_dep_tree(config: &Config,
-                  metadata: &JsonValue,
+                  metadatas: &[JsonValue],
+                  all_metadatas: &[&JsonValue],
                   packages: &Packages,
                   dep_tree: &mut DepTree)
                   -> RtResult<()> {
     let root_ids = {
-        let workspace_members = workspace_members(metadata)?;
+        let workspace_members = match config.bin {
+            Some(ref bin_name) => {
+                let owner = bin_owner(bin_name, metadatas)?;
+                verbose!(config, "Found owner of binary target '{}': {}", bin_name, owner);
+                vec![owner]
+            }
+
+            None => {
+                let mut members = Vec::new();
+                for metadata in metadatas {
+                    let metadata_members = if config.default_members_only {
+                        workspace_default_members(metadata)?
+                    } else {
+                        workspace_members(metadata)?
+                    };
+
+                    for member in metadata_members {
+                        if ! members.contains(&member) {
+                            members.push(member);
+                        }
+                    }
+                }
+
+                members
+            }
+        };
+
         verbose!(config, "Found workspace members: {:?}", workspace_members);
 
         let mut ids = Vec::with_capacity(workspace_members.len());
         for member in &workspace_members {
-            let member_package = package(member, packages)?;
+            let member_package = match packages.get(member) {
+                Some(package) => package,
+                None => {
+                    verbose!(config, "Skipping workspace member {} without supported targets", member);
+                    continue;
+                }
+            };
+
             ids.push(member_package.source_id);
             if config.omit_deps {
                 let is_root = true;
-                let source = Source::new(member_package.source_id, member,
-                                         member_package.source_path, is_root, config)?;
+                let mut source = Source::new(member_package.source_id, member, &member_package.source_path,
+                                             is_root, member_package.cargo_source.as_deref(), config)?;
+                source.test_paths = member_package.test_paths.clone();
+                source.out_files = member_package.out_files.clone();
+                source.target_files = member_package.target_files.clone();
                 dep_tree.set_source(source, vec![]);
             }
         }
@@ -99,18 +330,34 @@ fn build_dep_tree(config: &Config,
         return Ok(());
     }
 
-    let nodes = {
+    // the nodes of the workspaces come first, so that they're
+    // preferred to the ones of the followed path workspaces
+    let mut nodes = Vec::new();
+    for metadata in all_metadatas {
         let resolve = as_object_from_value("resolve", metadata)?;
-        as_array_from_object("nodes", resolve)?
-    };
+        nodes.extend(as_array_from_object("nodes", resolve)?);
+    }
 
+    let mut visited_ids = FnvHashSet::default();
     for node in nodes {
         let node_version = {
             let id = as_str_from_value("id", node)?;
             SourceVersion::parse_from_id(id)?
         };
 
-        let node_package = package(&node_version, packages)?;
+        // packages without supported targets aren't part of the tree
+        let node_package = match packages.get(&node_version) {
+            Some(package) => package,
+            None => {
+                verbose!(config, "Skipping {} without supported targets", node_version);
+                continue;
+            }
+        };
+
+        // a node shared by multiple workspaces
+        if ! visited_ids.insert(node_package.source_id) {
+            continue;
+        }
 
         let dep_ids = {
             let dependencies = as_array_from_value("dependencies", node)?;
@@ -131,36 +378,82 @@ fn build_dep_tree(config: &Config,
                 verbose!(config, "Found dependencies of {}: {:?}", node_version, dep_versions);
             }
 
-            let mut ids = Vec::with_capacity(dep_versions.len());
-            for version in &dep_versions {
-                ids.push(package(version, packages)?.source_id);
-            }
-
-            ids
+            dep_versions.iter()
+                .filter_map(|version| packages.get(version))
+                .map(|package| package.source_id)
+                .collect()
         };
 
         verbose!(config, "Building tree for {}", node_version);
 
         let is_root = root_ids.iter().find(|id| **id == node_package.source_id) != None;
-        let source = Source::new(node_package.source_id, &node_version, node_package.source_path, is_root, config)?;
+        let mut source = Source::new(node_package.source_id, &node_version, &node_package.source_path,
+                                     is_root, node_package.cargo_source.as_deref(), config)?;
+        source.out_files = node_package.out_files.clone();
+        if is_root {
+            source.test_paths = node_package.test_paths.clone();
+            source.target_files = node_package.target_files.clone();
+        }
+
         dep_tree.set_source(source, dep_ids);
     }
 
     Ok(())
 }
 
-fn package<'a>(source_version: &SourceVersion<'a>, packages: &'a Packages) -> RtResult<&'a Package<'a>> {
-    packages.get(&source_version)
-        .ok_or(format!("Couldn't find package for {}", source_version).into())
+/// The matcher of the glob pattern 'glob', in which '*' matches any characters
+/// except '/', '?' a single one and '**/' any directories.
+fn glob_matcher(glob: &str) -> RtResult<GlobMatcher> {
+    GlobBuilder::new(glob)
+        .literal_separator(true)
+        .build()
+        .map(|g| g.compile_matcher())
+        .map_err(|err| format!("Invalid glob pattern '{}': {}", glob, err).into())
 }
 
-fn source_path<'a>(config: &Config, package: &'a JsonValue) -> RtResult<Option<&'a Path>> {
+/// The rust source files in the build script output directory of 'source_version', whose
+/// paths relative to the output directory match one of the 'out_dir_globs' of its crate config.
+fn out_files(config: &Config, source_version: &SourceVersion, target_dirs: &[PathBuf]) -> RtResult<Vec<PathBuf>> {
+    let globs = match config.crate_config(source_version.name) {
+        Some(crate_config) if ! crate_config.out_dir_globs.is_empty() => crate_config.out_dir_globs.iter()
+            .map(|g| glob_matcher(g))
+            .collect::<RtResult<Vec<_>>>()?,
+
+        _ => return Ok(Vec::new())
+    };
+
+    let mut files = Vec::new();
+    for target_dir in target_dirs {
+        let out_dir = match build_out_dir(target_dir, source_version.name)? {
+            Some(dir) => dir,
+            None => continue
+        };
+
+        for file in rust_files(&out_dir, &config.exclude_paths)? {
+            let rel_file = file.strip_prefix(&out_dir).unwrap_or(&file).to_string_lossy().replace('\\', "/");
+            if globs.iter().any(|g| g.is_match(&rel_file)) {
+                files.push(file);
+            }
+        }
+    }
+
+    if files.is_empty() {
+        verbose!(config, "Couldn't find build script output files of {} matching 'out_dir_globs', is it built?", source_version);
+    } else {
+        verbose!(config, "Found build script output files of {}: {:?}", source_version, files);
+    }
+
+    Ok(files)
+}
+
+fn source_path(config: &Config, package: &JsonValue) -> RtResult<Option<PathBuf>> {
     let targets = as_array_from_value("targets", package)?;
 
     let manifest_dir = {
-        let manifest_path = as_str_from_value("manifest_path", package).map(Path::new)?;
+        let manifest_path = manifest_path(package)?;
 
         manifest_path.parent()
+            .map(Path::to_path_buf)
             .ok_or(format!("Couldn't get directory of path '{:?}'", manifest_path.display()))?
     };
 
@@ -171,7 +464,7 @@ fn source_path<'a>(config: &Config, package: &'a JsonValue) -> RtResult<Option<&
             let kind_str = kind.as_str()
                 .ok_or(format!("Expected 'kind' of type string but found: {}", to_string_pretty(kind)))?;
 
-            if kind_str != "bin" && ! kind_str.contains("lib") && kind_str != "proc-macro" && kind_str != "test" {
+            if ! is_supported_kind(config, kind_str) {
                 verbose!(config, "Unsupported target kind: {}", kind_str);
                 continue;
             }
@@ -184,7 +477,7 @@ fn source_path<'a>(config: &Config, package: &'a JsonValue) -> RtResult<Option<&
             }
 
             if src_path.is_relative() {
-                src_path = manifest_dir;
+                src_path = &manifest_dir;
             }
 
             if ! src_path.is_dir() {
@@ -192,13 +485,97 @@ fn source_path<'a>(config: &Config, package: &'a JsonValue) -> RtResult<Option<&
                                    src_path.display(), to_string_pretty(target), to_string_pretty(package)).into());
             }
 
-            return Ok(Some(src_path));
+            return Ok(Some(src_path.to_path_buf()));
         }
     }
 
     Ok(None)
 }
 
+fn is_supported_kind(config: &Config, kind: &str) -> bool {
+    kind == "bin" || kind == "proc-macro" || kind == "test" || config.lib_kinds.iter().any(|k| k == kind)
+}
+
+/// Returns the crate root files of the targets of 'package' with a supported kind for
+/// '--index-target-files-only', without the test targets for '--split-tests', whose
+/// files get their own tags file.
+fn target_files(config: &Config, package: &JsonValue) -> RtResult<Vec<PathBuf>> {
+    let manifest_path = manifest_path(package)?;
+    let manifest_dir = manifest_path.parent().unwrap_or(Path::new("."));
+    let mut files = Vec::new();
+    for target in as_array_from_value("targets", package)? {
+        let kinds = as_array_from_value("kind", target)?;
+        let is_target = kinds.iter()
+            .filter_map(JsonValue::as_str)
+            .any(|k| is_supported_kind(config, k) && ! (config.split_tests && k == "test"));
+
+        if ! is_target {
+            continue;
+        }
+
+        let src_path = manifest_dir.join(as_str_from_value("src_path", target)?);
+        if ! files.contains(&src_path) {
+            files.push(src_path);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Returns the source files of the test targets of 'package', or their
+/// directories if they aren't in 'source_path', e.g. the 'tests' directory.
+fn test_paths(package: &JsonValue, source_path: &Path) -> RtResult<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for target in as_array_from_value("targets", package)? {
+        let kinds = as_array_from_value("kind", target)?;
+        if ! kinds.iter().any(|k| k.as_str() == Some("test")) {
+            continue;
+        }
+
+        let src_path = as_str_from_value("src_path", target).map(Path::new)?;
+        let path = match src_path.parent() {
+            Some(dir) if dir != source_path => dir,
+            _ => src_path
+        };
+
+        if path.is_absolute() && ! paths.iter().any(|p| p == path) {
+            paths.push(path.to_path_buf());
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Makes the 'manifest_path' of the packages of 'metadata' absolute, which is normally
+/// already the case, but might be relative for some cargo versions or '--manifest-path'
+/// invocations. Relative paths are relative to 'cargo_dir', the directory cargo was run in.
+pub fn resolve_manifest_paths(metadata: &mut JsonValue, cargo_dir: &Path) -> RtResult<()> {
+    let packages = match metadata.get_mut("packages").and_then(JsonValue::as_array_mut) {
+        Some(packages) => packages,
+        None => return Ok(())
+    };
+
+    for package in packages {
+        let manifest_path = PathBuf::from(as_str_from_value("manifest_path", package)?);
+        if manifest_path.is_absolute() {
+            continue;
+        }
+
+        let resolved_path = cargo_dir.join(&manifest_path)
+            .canonicalize()
+            .map_err(|err| format!("Couldn't resolve relative manifest path '{}' against directory '{}': {}",
+                                   manifest_path.display(), cargo_dir.display(), err))?;
+
+        package["manifest_path"] = JsonValue::String(resolved_path.to_string_lossy().into_owned());
+    }
+
+    Ok(())
+}
+
+fn manifest_path(package: &JsonValue) -> RtResult<PathBuf> {
+    as_str_from_value("manifest_path", package).map(PathBuf::from)
+}
+
 fn to_string_pretty(value: &JsonValue) -> String {
     serde_json::to_string_pretty(value).unwrap_or(String::new())
 }
@@ -226,3 +603,73 @@ fn as_array_from_object<'a>(entry: &str, object: &'a JsonObject) -> RtResult<&'a
           .and_then(JsonValue::as_array)
           .ok_or(format!("Couldn't find array entry '{}' in:\n{:?}", entry, object).into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matcher_matches_out_dir_files() {
+        let bindings = glob_matcher("**/bindings.rs").unwrap();
+        assert!(bindings.is_match("bindings.rs"));
+        assert!(bindings.is_match("ffi/gen/bindings.rs"));
+        assert!(! bindings.is_match("ffi/other_bindings.rs"));
+
+        let star = glob_matcher("gen/*.rs").unwrap();
+        assert!(star.is_match("gen/lib.rs"));
+        assert!(! star.is_match("gen/sub/lib.rs"));
+
+        let single = glob_matcher("mod_?.rs").unwrap();
+        assert!(single.is_match("mod_a.rs"));
+        assert!(! single.is_match("mod_ab.rs"));
+        assert!(! single.is_match("mod_/.rs"));
+
+        let class = glob_matcher("[ab]_[!x].rs").unwrap();
+        assert!(class.is_match("a_y.rs"));
+        assert!(! class.is_match("c_y.rs"));
+        assert!(! class.is_match("b_x.rs"));
+
+        let escaped = glob_matcher("\\*.rs").unwrap();
+        assert!(escaped.is_match("*.rs"));
+        assert!(! escaped.is_match("lib.rs"));
+    }
+
+    #[test]
+    fn glob_matcher_rejects_invalid_globs() {
+        assert!(glob_matcher("gen/[a.rs").is_err());
+    }
+
+    #[test]
+    fn resolve_manifest_paths_against_cargo_dir() {
+        let workspace_dir = tempfile::tempdir().unwrap();
+        let member_dir = workspace_dir.path().join("member");
+        fs::create_dir(&member_dir).unwrap();
+        fs::write(member_dir.join("Cargo.toml"), "").unwrap();
+
+        // the start directory, e.g. a source directory of the member, differs from the
+        // workspace directory, which cargo was run in and reports relative paths against
+        let start_dir = member_dir.join("src");
+        fs::create_dir(&start_dir).unwrap();
+
+        let mut metadata = serde_json::json!({
+            "packages": [
+                { "manifest_path": "member/Cargo.toml" },
+                { "manifest_path": "/abs/dep/Cargo.toml" }
+            ]
+        });
+
+        resolve_manifest_paths(&mut metadata, workspace_dir.path()).unwrap();
+
+        let packages = metadata["packages"].as_array().unwrap();
+        assert_eq!(manifest_path(&packages[0]).unwrap(), member_dir.join("Cargo.toml").canonicalize().unwrap());
+        assert_eq!(manifest_path(&packages[1]).unwrap(), PathBuf::from("/abs/dep/Cargo.toml"));
+        assert!(! start_dir.join("member/Cargo.toml").exists());
+    }
+
+    #[test]
+    fn resolve_manifest_paths_fails_for_missing_manifest() {
+        let workspace_dir = tempfile::tempdir().unwrap();
+        let mut metadata = serde_json::json!({ "packages": [{ "manifest_path": "missing/Cargo.toml" }] });
+        assert!(resolve_manifest_paths(&mut metadata, workspace_dir.path()).is_err());
+    }
+}
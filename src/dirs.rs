@@ -1,4 +1,6 @@
+use std::env;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 use extern_dirs;
 
@@ -35,6 +37,108 @@ pub fn rusty_tags_locks_dir() -> RtResult<&'static Path> {
         .map_err(|err| err.clone())
 }
 
+/// a cache directory on a local file system, used instead of `rusty_tags_cache_dir` if the
+/// home directory is slow, in `$XDG_RUNTIME_DIR` or otherwise in the temporary directory
+/// of the system
+pub fn rusty_tags_local_cache_dir() -> RtResult<PathBuf> {
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute() && dir.is_dir());
+
+    let dir = match runtime_dir {
+        Some(runtime_dir) => runtime_dir.join("rusty-tags-cache"),
+        None => {
+            let dir_name = match env::var("USER") {
+                Ok(user) => format!("rusty-tags-cache-{}", user),
+                Err(_)   => "rusty-tags-cache".to_string()
+            };
+
+            env::temp_dir().join(dir_name)
+        }
+    };
+
+    ensure_private_dir(&dir)?;
+    Ok(dir)
+}
+
+/// creates the directory `dir` only accessible by the current user, or checks that the existing
+/// one is owned by the current user and isn't accessible by others, because its predictable
+/// path in a shared directory might have been created by another user to inject tags
+fn ensure_private_dir(dir: &Path) -> RtResult<()> {
+    let metadata = match fs::symlink_metadata(dir) {
+        Ok(metadata) => metadata,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+            let mut builder = fs::DirBuilder::new();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::DirBuilderExt;
+                builder.mode(0o700);
+            }
+
+            // fails if another user created the directory in the meantime
+            builder.create(dir)
+                .map_err(|err| format!("Couldn't create cache directory '{}': {}", dir.display(), err))?;
+
+            return Ok(());
+        }
+
+        Err(err) => return Err(err.into())
+    };
+
+    if ! metadata.is_dir() {
+        return Err(format!("The cache directory '{}' isn't a directory!", dir.display()).into());
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        if metadata.uid() != unsafe { libc::geteuid() } {
+            return Err(format!("The cache directory '{}' isn't owned by the current user!", dir.display()).into());
+        }
+
+        if metadata.mode() & 0o077 != 0 {
+            return Err(format!("The cache directory '{}' is accessible by other users, its mode has to be 0700!",
+                               dir.display()).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the name of the network file system `path` is on, this
+/// is only a best-effort detection and only supported on linux.
+#[cfg(target_os = "linux")]
+pub fn network_file_system(path: &Path) -> Option<&'static str> {
+    use std::ffi::CString;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    // the magic numbers from 'man 2 statfs'
+    match stat.f_type as u32 {
+        0x6969 => Some("nfs"),
+        0x517b => Some("smb"),
+        0xfe534d42 => Some("smb2"),
+        0xff534d42 => Some("cifs"),
+        0x5346414f => Some("afs"),
+        0x73757245 => Some("coda"),
+        0x564c => Some("ncp"),
+        0x65735546 => Some("fuse"),
+        _ => None
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn network_file_system(_path: &Path) -> Option<&'static str> {
+    None
+}
+
 fn home_dir() -> RtResult<PathBuf> {
     HOME_DIR.clone()
 }
@@ -73,3 +177,43 @@ fn rusty_tags_dir_internal() -> RtResult<PathBuf> {
 
     Ok(dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_private_dir_creates_dir() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path().join("cache");
+        ensure_private_dir(&dir).unwrap();
+        assert!(dir.is_dir());
+
+        // the created directory is accepted later on
+        ensure_private_dir(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ensure_private_dir_rejects_accessible_dirs() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path().join("cache");
+        ensure_private_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(ensure_private_dir(&dir).is_err());
+
+        let link = tmp_dir.path().join("link");
+        let private_dir = tmp_dir.path().join("private");
+        ensure_private_dir(&private_dir).unwrap();
+        symlink(&private_dir, &link).unwrap();
+        assert!(ensure_private_dir(&link).is_err());
+
+        let file = tmp_dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        assert!(ensure_private_dir(&file).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use rt_result::RtResult;
+use types::Source;
+use config::Config;
+use files::rust_files;
+use tags::read_file;
+
+/// The attributes of a rustdoc code block which still mark it as rust code.
+const RUST_ATTRIBUTES: [&str; 11] = ["rust", "ignore", "should_panic", "no_run", "compile_fail",
+                                     "test_harness", "standalone_crate", "edition2015", "edition2018",
+                                     "edition2021", "edition2024"];
+
+/// A doc-test code block of a source file.
+struct DocTest {
+    /// the line of the opening fence of the code block, starting at 1
+    line: usize,
+
+    /// the code of the doc test, with hidden lines made visible
+    code: Vec<String>
+}
+
+/// Extracts the code of the doc-tests in the doc comments of 'source' into
+/// a directory in the cache directory, which mirrors the source files.
+///
+/// Returns the directory, which can be tagged as an extra source directory
+/// of 'source', or 'None' if 'source' hasn't any doc-tests.
+pub fn extract_doc_tests(config: &Config, source: &Source) -> RtResult<Option<PathBuf>> {
+    let doc_tests_dir = config.cache_dir.join(format!("{}-{}.doctests", source.name, source.hash));
+    if doc_tests_dir.is_dir() {
+        fs::remove_dir_all(&doc_tests_dir)?;
+    }
+
+    let mut num_doc_tests = 0;
+    for src_file in rust_files(&source.dir, &config.exclude_paths)? {
+        let doc_tests = parse_doc_tests(&read_file(&src_file)?);
+        if doc_tests.is_empty() {
+            continue;
+        }
+
+        let rel_file = src_file.strip_prefix(&source.dir).unwrap_or(&src_file);
+        let doc_tests_file = doc_tests_dir.join(rel_file);
+        if let Some(parent) = doc_tests_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        write_doc_tests(&doc_tests_file, rel_file, &doc_tests)?;
+        num_doc_tests += doc_tests.len();
+    }
+
+    if num_doc_tests == 0 {
+        return Ok(None);
+    }
+
+    verbose!(config, "\nExtracted {} doc-tests of '{}' into:\n   {}", num_doc_tests, source.name, doc_tests_dir.display());
+    Ok(Some(doc_tests_dir))
+}
+
+fn write_doc_tests(doc_tests_file: &Path, src_file: &Path, doc_tests: &[DocTest]) -> RtResult<()> {
+    let mut writer = BufWriter::new(File::create(doc_tests_file)?);
+    for doc_test in doc_tests {
+        writeln!(writer, "// doc-test of {}:{}", src_file.display(), doc_test.line)?;
+        for line in &doc_test.code {
+            writeln!(writer, "{}", line)?;
+        }
+
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+/// Parses the rust code blocks of the '///' and '//!' doc comments in 'contents'.
+fn parse_doc_tests(contents: &str) -> Vec<DocTest> {
+    let mut doc_tests = Vec::new();
+
+    // the fence and the doc-test of the currently open code block,
+    // the doc-test is 'None' if the code block isn't rust code
+    let mut open_block: Option<(&str, Option<DocTest>)> = None;
+
+    for (idx, line) in contents.lines().enumerate() {
+        let doc_line = match doc_comment_line(line) {
+            Some(doc_line) => doc_line,
+            None => {
+                // a code block can't continue after the end of the doc comment
+                if let Some((_, Some(doc_test))) = open_block.take() {
+                    doc_tests.push(doc_test);
+                }
+
+                continue;
+            }
+        };
+
+        let trimmed = doc_line.trim_start();
+        let closes_block = match open_block {
+            Some((fence, _)) => trimmed.starts_with(fence) && trimmed.trim_start_matches(['`', '~']).trim().is_empty(),
+            None => false
+        };
+
+        if closes_block {
+            if let Some((_, Some(doc_test))) = open_block.take() {
+                doc_tests.push(doc_test);
+            }
+        } else if let Some((_, ref mut doc_test)) = open_block {
+            if let Some(ref mut doc_test) = *doc_test {
+                doc_test.code.push(unhide_line(doc_line).to_string());
+            }
+        } else if let Some(fence) = opening_fence(trimmed) {
+            let doc_test = if is_rust_block(&trimmed[fence.len()..]) {
+                Some(DocTest { line: idx + 1, code: Vec::new() })
+            } else {
+                None
+            };
+
+            open_block = Some((fence, doc_test));
+        }
+    }
+
+    if let Some((_, Some(doc_test))) = open_block {
+        doc_tests.push(doc_test);
+    }
+
+    doc_tests
+}
+
+/// Returns the content of a '///' or '//!' doc comment line.
+fn doc_comment_line(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line.starts_with("////") {
+        return None;
+    }
+
+    line.strip_prefix("///")
+        .or_else(|| line.strip_prefix("//!"))
+        .map(|doc| doc.strip_prefix(' ').unwrap_or(doc))
+}
+
+fn opening_fence(line: &str) -> Option<&'static str> {
+    if line.starts_with("```") {
+        Some("```")
+    } else if line.starts_with("~~~") {
+        Some("~~~")
+    } else {
+        None
+    }
+}
+
+/// If a code block with the info string 'info', e.g. 'rust,no_run', contains rust code,
+/// which is the case for code blocks without a language.
+fn is_rust_block(info: &str) -> bool {
+    let mut attributes = info.split(|c: char| c == ',' || c.is_whitespace())
+        .map(|a| a.trim_start_matches('.'))
+        .filter(|a| ! a.is_empty() && ! a.starts_with('{') && ! a.starts_with('}'));
+
+    attributes.all(|a| RUST_ATTRIBUTES.contains(&a) || a.starts_with("ignore-"))
+}
+
+/// Makes a hidden line of a doc-test ('# let x = 1;') visible.
+fn unhide_line(line: &str) -> &str {
+    let trimmed = line.trim_start();
+    if trimmed == "#" {
+        ""
+    } else if trimmed.starts_with("##") {
+        &trimmed[1..]
+    } else {
+        trimmed.strip_prefix("# ").unwrap_or(line)
+    }
+}
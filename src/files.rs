@@ -0,0 +1,503 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use rt_result::RtResult;
+use regex::Regex;
+use cfg::CfgTarget;
+
+/// Returns all rust source files in the directory hierarchy starting at 'dir',
+/// sorted by their path. Hidden directories, like '.git', cargo target directories
+/// and the files and directories matching one of 'exclude_paths' are skipped.
+pub fn rust_files(dir: &Path, exclude_paths: &[Regex]) -> RtResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                if ! is_hidden(&path) && ! is_cargo_target_dir(&path) && ! is_excluded(&path, true, exclude_paths) {
+                    dirs.push(path);
+                }
+            } else if path.extension() == Some("rs".as_ref()) && ! is_excluded(&path, false, exclude_paths) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the directories in the directory hierarchy starting at the source directory
+/// 'dir' of a crate, which don't belong to the crate: cargo target directories and the
+/// directories of nested packages, e.g. of a workspace member included as git submodule,
+/// which might also have its own target directory. Each crate might have its own target
+/// directories, so they're searched for every crate instead of only excluding the target
+/// directory of the workspace.
+pub fn foreign_dirs(dir: &Path) -> RtResult<Vec<PathBuf>> {
+    let mut foreign_dirs = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if ! path.is_dir() || is_hidden(&path) {
+                continue;
+            }
+
+            if is_cargo_target_dir(&path) || path.join("Cargo.toml").is_file() {
+                foreign_dirs.push(path);
+            } else {
+                dirs.push(path);
+            }
+        }
+    }
+
+    foreign_dirs.sort();
+    Ok(foreign_dirs)
+}
+
+/// Returns the path of the absolute 'path' relative to the absolute directory 'base_dir',
+/// e.g. '../src/lib.rs' for '/ws/src/lib.rs' relative to '/ws/tags'.
+pub fn relative_path(base_dir: &Path, path: &Path) -> PathBuf {
+    let base: Vec<_> = base_dir.components().collect();
+    let target: Vec<_> = path.components().collect();
+    let common = base.iter().zip(&target).take_while(|&(b, t)| b == t).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+
+    for component in &target[common..] {
+        relative.push(component.as_os_str());
+    }
+
+    relative
+}
+
+/// Returns 'path' without '.' components and with the '..' components applied to
+/// their preceding components, without resolving symbolic links, e.g. '/ws/src/lib.rs'
+/// for '/ws/tags/../src/./lib.rs'.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => { normalized.pop(); }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+
+            component => normalized.push(component.as_os_str())
+        }
+    }
+
+    normalized
+}
+
+/// Returns the path of the absolute 'path' relative to the most specific one of the
+/// directories 'roots' containing it, for '--relative-roots', e.g. 'src/lib.rs' for
+/// '/repo/crates/a/src/lib.rs' with the roots '/repo' and '/repo/crates/a'.
+pub fn relative_to_roots(path: &Path, roots: &[PathBuf]) -> Option<PathBuf> {
+    roots.iter()
+        .filter(|r| path.starts_with(r))
+        .max_by_key(|r| r.components().count())
+        .and_then(|r| path.strip_prefix(r).ok())
+        .map(Path::to_path_buf)
+}
+
+/// Returns the 'prelude' module directories and 'prelude.rs' module files in the
+/// directory hierarchy starting at 'dir', e.g. 'std/src/prelude' of the standard library.
+pub fn prelude_modules(dir: &Path) -> RtResult<Vec<PathBuf>> {
+    let mut modules = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let is_dir = path.is_dir();
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if (is_dir && name == "prelude") || (! is_dir && name == "prelude.rs") {
+                modules.push(path);
+            } else if is_dir && ! is_hidden(&path) {
+                dirs.push(path);
+            }
+        }
+    }
+
+    modules.sort();
+    Ok(modules)
+}
+
+/// If 'dir' is a target directory of cargo, detected by the
+/// files cargo creates in every target directory.
+pub fn is_cargo_target_dir(dir: &Path) -> bool {
+    dir.join("CACHEDIR.TAG").is_file() || dir.join(".rustc_info.json").is_file()
+}
+
+/// If 'path' matches one of 'exclude_paths'. The regexes are matched against
+/// the whole path with '/' as separator, which ends with a '/' for directories.
+pub fn is_excluded(path: &Path, is_dir: bool, exclude_paths: &[Regex]) -> bool {
+    if exclude_paths.is_empty() {
+        return false;
+    }
+
+    let mut path = path.to_string_lossy().replace('\\', "/");
+    if is_dir && ! path.ends_with('/') {
+        path.push('/');
+    }
+
+    exclude_paths.iter().any(|r| r.is_match(&path))
+}
+
+/// Returns the rust source files in the directory hierarchy starting at 'dir'
+/// which only contain test code: files with a '#![cfg(test)]' attribute and the
+/// files of modules declared with '#[cfg(test)]', e.g. '#[cfg(test)] mod tests;'.
+pub fn cfg_test_files(dir: &Path, exclude_paths: &[Regex]) -> RtResult<Vec<PathBuf>> {
+    cfg_gated_files(dir, exclude_paths, &|cfg| cfg.trim() == "test")
+}
+
+/// Returns the rust source files in the directory hierarchy starting at 'dir' which
+/// are only compiled for other targets than 'target', e.g. the files of a module declared
+/// with '#[cfg(windows)]' for a linux target. Code gated inside of a file isn't detected.
+pub fn inactive_cfg_files(dir: &Path, exclude_paths: &[Regex], target: &CfgTarget) -> RtResult<Vec<PathBuf>> {
+    cfg_gated_files(dir, exclude_paths, &|cfg| target.is_active(cfg) == Some(false))
+}
+
+/// Returns the crate root files 'crate_roots' of the targets of a package and the files of
+/// the modules declared in them, recursively, sorted by their path. This is best-effort: the
+/// modules are found by their 'mod name;' declarations, including a preceding '#[path = "..."]'
+/// attribute, but not the ones declared by macros or in inline modules.
+pub fn module_files(crate_roots: &[PathBuf], exclude_paths: &[Regex]) -> RtResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    // the files with a flag if the files of their modules are in their directory
+    let mut pending: Vec<(PathBuf, bool)> = crate_roots.iter().map(|f| (f.clone(), true)).collect();
+    while let Some((file, is_in_modules_dir)) = pending.pop() {
+        if files.contains(&file) || ! file.is_file() || is_excluded(&file, false, exclude_paths) {
+            continue;
+        }
+
+        let file_dir = file.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let modules_dir = if is_in_modules_dir { file_dir.clone() } else { modules_dir(&file) };
+        let contents = String::from_utf8_lossy(&fs::read(&file)?).into_owned();
+        let mut module_path = None;
+        for line in contents.lines() {
+            let line = line.trim();
+            if let Some(path) = path_attribute(line) {
+                module_path = Some(path);
+                continue;
+            }
+
+            // further attributes might follow the path attribute
+            if line.is_empty() || line.starts_with("#[") || line.starts_with("//") {
+                continue;
+            }
+
+            if let Some(module) = module_declaration(line) {
+                match module_path {
+                    Some(path) => pending.push((file_dir.join(path), true)),
+                    None => {
+                        let module_file = modules_dir.join(format!("{}.rs", module));
+                        if module_file.is_file() {
+                            pending.push((module_file, false));
+                        } else {
+                            pending.push((modules_dir.join(module).join("mod.rs"), true));
+                        }
+                    }
+                }
+            }
+
+            module_path = None;
+        }
+
+        files.push(file);
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the build script output directory ('OUT_DIR') of the package 'package_name'
+/// in the cargo target directory 'target_dir', which is e.g. 'target/debug/build/<name>-<hash>/out'
+/// or 'target/<triple>/release/build/<name>-<hash>/out'. If there're multiple ones, e.g.
+/// of different profiles or versions, then the most recently modified one is returned.
+pub fn build_out_dir(target_dir: &Path, package_name: &str) -> RtResult<Option<PathBuf>> {
+    if ! target_dir.is_dir() {
+        return Ok(None);
+    }
+
+    let mut build_dirs = Vec::new();
+    for entry in fs::read_dir(target_dir)? {
+        let dir = entry?.path();
+        if dir.join("build").is_dir() {
+            build_dirs.push(dir.join("build"));
+        } else if dir.is_dir() {
+            for entry in fs::read_dir(&dir)? {
+                let build_dir = entry?.path().join("build");
+                if build_dir.is_dir() {
+                    build_dirs.push(build_dir);
+                }
+            }
+        }
+    }
+
+    let prefix = format!("{}-", package_name);
+    let mut newest_out_dir: Option<(PathBuf, SystemTime)> = None;
+    for build_dir in &build_dirs {
+        for entry in fs::read_dir(build_dir)? {
+            let path = entry?.path();
+            let is_package_dir = path.file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix(&prefix))
+                .is_some_and(|hash| ! hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()));
+
+            let out_dir = path.join("out");
+            if ! is_package_dir || ! out_dir.is_dir() {
+                continue;
+            }
+
+            let modified = fs::metadata(&out_dir)?.modified()?;
+            if newest_out_dir.as_ref().is_none_or(|&(_, newest)| modified > newest) {
+                newest_out_dir = Some((out_dir, modified));
+            }
+        }
+    }
+
+    Ok(newest_out_dir.map(|(dir, _)| dir))
+}
+
+/// Returns the rust source files in the directory hierarchy starting at 'dir'
+/// which are bigger than 'max_size' bytes, with their sizes.
+pub fn oversized_files(dir: &Path, exclude_paths: &[Regex], max_size: u64) -> RtResult<Vec<(PathBuf, u64)>> {
+    let mut oversized = Vec::new();
+    for file in rust_files(dir, exclude_paths)? {
+        let size = fs::metadata(&file)?.len();
+        if size > max_size {
+            oversized.push((file, size));
+        }
+    }
+
+    Ok(oversized)
+}
+
+/// Returns the files with an inner cfg attribute and the files of the modules declared
+/// with a cfg attribute, whose predicate is gated by 'is_gated'.
+fn cfg_gated_files(dir: &Path, exclude_paths: &[Regex], is_gated: &dyn Fn(&str) -> bool) -> RtResult<Vec<PathBuf>> {
+    let files = rust_files(dir, exclude_paths)?;
+    let mut gated_files = Vec::new();
+    for file in &files {
+        let contents = String::from_utf8_lossy(&fs::read(file)?).into_owned();
+        let inner_gated = contents.lines()
+            .filter_map(|l| cfg_attribute(l.trim(), "#![cfg("))
+            .any(|(cfg, _)| is_gated(cfg));
+
+        if inner_gated {
+            gated_files.push(file.clone());
+        }
+
+        let modules_dir = modules_dir(file);
+        for module in cfg_gated_modules(&contents, is_gated) {
+            let module_file = modules_dir.join(format!("{}.rs", module));
+            let module_dir = modules_dir.join(module);
+            gated_files.extend(files.iter()
+                .filter(|f| **f == module_file || f.starts_with(&module_dir))
+                .cloned());
+        }
+    }
+
+    gated_files.sort();
+    gated_files.dedup();
+    Ok(gated_files)
+}
+
+/// The directory of the files of the modules declared in 'file'.
+fn modules_dir(file: &Path) -> PathBuf {
+    let dir = file.parent().unwrap_or(Path::new("."));
+    match file.file_stem().and_then(|s| s.to_str()) {
+        Some("lib") | Some("main") | Some("mod") | None => dir.to_path_buf(),
+        Some(stem) => dir.join(stem)
+    }
+}
+
+/// Returns the names of the modules declared with a preceding cfg
+/// attribute gated by 'is_gated', e.g. '#[cfg(test)]'.
+fn cfg_gated_modules<'a>(contents: &'a str, is_gated: &dyn Fn(&str) -> bool) -> Vec<&'a str> {
+    let mut modules = Vec::new();
+    let mut gated = false;
+    for line in contents.lines() {
+        let mut line = line.trim();
+        while let Some((cfg, rest)) = cfg_attribute(line, "#[cfg(") {
+            gated = gated || is_gated(cfg);
+            line = rest.trim();
+        }
+
+        // further attributes might follow the cfg attribute
+        if line.is_empty() || line.starts_with("#[") {
+            continue;
+        }
+
+        if gated {
+            if let Some(module) = module_declaration(line) {
+                modules.push(module);
+            }
+        }
+
+        gated = false;
+    }
+
+    modules
+}
+
+/// Splits a line starting with the cfg attribute 'prefix', '#[cfg(' or '#![cfg(',
+/// into the predicate of the attribute and the rest of the line.
+fn cfg_attribute<'a>(line: &'a str, prefix: &str) -> Option<(&'a str, &'a str)> {
+    let predicate = line.strip_prefix(prefix)?;
+    let mut depth = 0;
+    for (idx, c) in predicate.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => return predicate[idx + 1..].strip_prefix(']').map(|rest| (&predicate[..idx], rest)),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Returns the path of a path attribute like '#[path = "foo_impl.rs"]'.
+fn path_attribute(line: &str) -> Option<&str> {
+    line.strip_prefix("#[path")?
+        .trim_start()
+        .strip_prefix('=')?
+        .trim()
+        .strip_suffix(']')?
+        .trim_end()
+        .strip_prefix('"')?
+        .strip_suffix('"')
+}
+
+/// Returns the module name of a module declaration like 'pub mod foo;'.
+fn module_declaration(line: &str) -> Option<&str> {
+    let line = line.strip_prefix("pub(crate) ")
+        .or_else(|| line.strip_prefix("pub "))
+        .unwrap_or(line);
+
+    let module = line.strip_prefix("mod ")?.trim().strip_suffix(';')?.trim();
+    if ! module.is_empty() && module.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        Some(module)
+    } else {
+        None
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.starts_with('.'))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_file(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn foreign_dirs_of_crate() {
+        let crate_dir = tempfile::tempdir().unwrap();
+        let dir = crate_dir.path();
+        create_file(&dir.join("Cargo.toml"), "");
+        create_file(&dir.join("src/lib.rs"), "");
+        create_file(&dir.join("src/nested/mod.rs"), "");
+
+        // a nested package, whose own sub directories aren't searched
+        create_file(&dir.join("crates/nested/Cargo.toml"), "");
+        create_file(&dir.join("crates/nested/target/CACHEDIR.TAG"), "");
+
+        // a workspace member included as git submodule
+        create_file(&dir.join("vendor/submodule/.git"), "gitdir: ../../.git/modules/submodule");
+        create_file(&dir.join("vendor/submodule/Cargo.toml"), "");
+
+        // the cargo target directory and one created with '--target-dir'
+        create_file(&dir.join("target/CACHEDIR.TAG"), "");
+        create_file(&dir.join("out/build/.rustc_info.json"), "");
+
+        // hidden directories are never searched
+        create_file(&dir.join(".git/modules/submodule/Cargo.toml"), "");
+
+        assert_eq!(foreign_dirs(dir).unwrap(), vec![
+            dir.join("crates/nested"),
+            dir.join("out/build"),
+            dir.join("target"),
+            dir.join("vendor/submodule")
+        ]);
+    }
+
+    #[test]
+    fn module_files_of_crate_roots() {
+        let crate_dir = tempfile::tempdir().unwrap();
+        let dir = crate_dir.path();
+        create_file(&dir.join("src/lib.rs"), "pub mod net;\nmod util;\n#[path = \"gen/bindings.rs\"]\n#[allow(dead_code)]\nmod ffi;\n// mod commented;\n");
+        create_file(&dir.join("src/net.rs"), "pub mod tcp;\n");
+        create_file(&dir.join("src/net/tcp.rs"), "");
+        create_file(&dir.join("src/util/mod.rs"), "mod fmt;\n");
+        create_file(&dir.join("src/util/fmt.rs"), "");
+        create_file(&dir.join("src/gen/bindings.rs"), "");
+        create_file(&dir.join("src/bin/tool.rs"), "mod args;\n");
+        create_file(&dir.join("src/bin/args.rs"), "");
+
+        // not declared as module of any target
+        create_file(&dir.join("src/unused.rs"), "");
+        create_file(&dir.join("src/commented.rs"), "");
+
+        let roots = vec![dir.join("src/lib.rs"), dir.join("src/bin/tool.rs")];
+        assert_eq!(module_files(&roots, &[]).unwrap(), vec![
+            dir.join("src/bin/args.rs"),
+            dir.join("src/bin/tool.rs"),
+            dir.join("src/gen/bindings.rs"),
+            dir.join("src/lib.rs"),
+            dir.join("src/net/tcp.rs"),
+            dir.join("src/net.rs"),
+            dir.join("src/util/fmt.rs"),
+            dir.join("src/util/mod.rs")
+        ]);
+
+        let exclude_paths = vec![Regex::new("/util/").unwrap()];
+        let files = module_files(&roots[..1], &exclude_paths).unwrap();
+        assert!(files.contains(&dir.join("src/net/tcp.rs")));
+        assert!(! files.iter().any(|f| f.starts_with(dir.join("src/util"))));
+    }
+
+    #[test]
+    fn module_files_of_missing_modules() {
+        let crate_dir = tempfile::tempdir().unwrap();
+        let dir = crate_dir.path();
+        create_file(&dir.join("src/main.rs"), "mod missing;\n");
+
+        assert_eq!(module_files(&[dir.join("src/main.rs")], &[]).unwrap(), vec![dir.join("src/main.rs")]);
+        assert!(module_files(&[dir.join("src/lib.rs")], &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(normalize_path(Path::new("/ws/tags/../src/./lib.rs")), PathBuf::from("/ws/src/lib.rs"));
+        assert_eq!(normalize_path(Path::new("/../ws/lib.rs")), PathBuf::from("/ws/lib.rs"));
+        assert_eq!(normalize_path(Path::new("../../ws/a/../lib.rs")), PathBuf::from("../../ws/lib.rs"));
+        assert_eq!(normalize_path(Path::new("./src/lib.rs")), PathBuf::from("src/lib.rs"));
+    }
+
+    #[test]
+    fn foreign_dirs_of_crate_without_any() {
+        let crate_dir = tempfile::tempdir().unwrap();
+        create_file(&crate_dir.path().join("src/lib.rs"), "");
+        create_file(&crate_dir.path().join("vendor/not-a-package/lib.rs"), "");
+        assert!(foreign_dirs(crate_dir.path()).unwrap().is_empty());
+    }
+}
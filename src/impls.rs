@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use serde_json;
+
+use rt_result::RtResult;
+use types::DepTree;
+use config::Config;
+use tags::{TagEntry, read_tag_entries};
+
+/// A trait implementation found in the tags.
+#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+struct ImplLocation {
+    /// the implemented trait as written in the source, e.g. 'fmt::Display'
+    #[serde(rename = "trait")]
+    trait_name: String,
+
+    /// the type implementing the trait
+    #[serde(rename = "type")]
+    type_name: String,
+
+    /// the source file of the implementation
+    path: String,
+
+    /// the source line of the implementation
+    pattern: String
+}
+
+/// Writes a JSON index of the tags of the roots of 'dep_tree' into 'index_file',
+/// which maps each trait name to the locations of its implementations.
+///
+/// This is best-effort and relies on the 'impl Trait for Type' pattern
+/// of the impl tags, which are only created by universal ctags.
+pub fn emit_impls(config: &Config, dep_tree: &DepTree, index_file: &Path) -> RtResult<()> {
+    info!(config, "Writing trait implementations to '{}' ...", index_file.display());
+
+    let mut impls: BTreeMap<String, Vec<ImplLocation>> = BTreeMap::new();
+    for root in dep_tree.roots() {
+        if ! root.tags_file.is_file() {
+            continue;
+        }
+
+        for entry in read_tag_entries(config, &root.tags_file)? {
+            if let Some(location) = impl_location(&entry) {
+                let key = trait_key(&location.trait_name);
+                impls.entry(key).or_default().push(location);
+            }
+        }
+    }
+
+    for locations in impls.values_mut() {
+        locations.sort();
+        locations.dedup();
+    }
+
+    verbose!(config, "Found implementations of {} traits", impls.len());
+
+    let writer = BufWriter::new(File::create(index_file)?);
+    serde_json::to_writer_pretty(writer, &impls)?;
+    Ok(())
+}
+
+fn impl_location(entry: &TagEntry) -> Option<ImplLocation> {
+    let (trait_name, type_name) = match parse_impl(&entry.pattern) {
+        Some(impl_names) => impl_names,
+        None => {
+            // universal ctags might have filled the 'implementation' field
+            let trait_name = entry.field("implementation")?;
+            (trait_name.to_string(), entry.name.clone())
+        }
+    };
+
+    Some(ImplLocation {
+        trait_name,
+        type_name,
+        path: entry.path.clone(),
+        pattern: entry.pattern.trim().to_string()
+    })
+}
+
+/// Parses the trait and the type of 'impl<...> Trait for Type'.
+fn parse_impl(line: &str) -> Option<(String, String)> {
+    let mut rest = line.trim_start();
+    if rest.starts_with("unsafe ") {
+        rest = rest["unsafe ".len()..].trim_start();
+    }
+
+    if ! rest.starts_with("impl") {
+        return None;
+    }
+
+    rest = &rest["impl".len()..];
+    if rest.starts_with('<') {
+        rest = &rest[closing_angle_bracket(rest)? + 1..];
+    } else if ! rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    // find the ' for ' outside of any generic arguments
+    let mut depth = 0;
+    let mut for_idx = None;
+    for (idx, chr) in rest.char_indices() {
+        match chr {
+            '<' => depth += 1,
+            '>' if is_arrow(rest, idx) => {}
+            '>' => depth -= 1,
+            _ if depth == 0 && rest[idx..].starts_with(" for ") => {
+                for_idx = Some(idx);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let for_idx = for_idx?;
+    let trait_name = rest[..for_idx].trim();
+    let type_name = {
+        let type_str = &rest[for_idx + " for ".len()..];
+        let end = type_str.find(" where").or_else(|| type_str.find('{')).unwrap_or(type_str.len());
+        type_str[..end].trim()
+    };
+
+    if trait_name.is_empty() || type_name.is_empty() {
+        return None;
+    }
+
+    Some((trait_name.to_string(), type_name.to_string()))
+}
+
+/// The key of a trait in the index: its name without the
+/// module path and without generic arguments.
+fn trait_key(trait_name: &str) -> String {
+    let without_args = trait_name.split('<').next().unwrap_or(trait_name);
+    without_args.rsplit("::").next().unwrap_or(without_args).trim().to_string()
+}
+
+/// The index of the '>' closing the '<' at the start of 's', the '>' of a '->',
+/// e.g. of the bound 'F: Fn() -> T', doesn't close anything.
+pub fn closing_angle_bracket(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, chr) in s.char_indices() {
+        match chr {
+            '<' => depth += 1,
+            '>' if is_arrow(s, idx) => {}
+            '>' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// If the '>' at 'idx' of 's' is part of a '->'.
+fn is_arrow(s: &str, idx: usize) -> bool {
+    s[..idx].ends_with('-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(trait_name: &str, type_name: &str) -> Option<(String, String)> {
+        Some((trait_name.to_string(), type_name.to_string()))
+    }
+
+    #[test]
+    fn parse_impl_of_trait_for_type() {
+        assert_eq!(parse_impl("impl Display for Point {"), pair("Display", "Point"));
+        assert_eq!(parse_impl("unsafe impl<T: Send> Send for Wrapper<T> {"), pair("Send", "Wrapper<T>"));
+        assert_eq!(parse_impl("impl<'a, T> From<Vec<T>> for Values<'a, T> where T: Clone {"), pair("From<Vec<T>>", "Values<'a, T>"));
+    }
+
+    #[test]
+    fn parse_impl_with_fn_bounds() {
+        assert_eq!(parse_impl("impl<F: Fn() -> T, T> Handler for Callback<F> {"), pair("Handler", "Callback<F>"));
+        assert_eq!(parse_impl("impl<F> Handler for Callback<F> where F: Fn(u8) -> Vec<u8> {"), pair("Handler", "Callback<F>"));
+        assert_eq!(parse_impl("impl Convert<fn() -> u8> for Table {"), pair("Convert<fn() -> u8>", "Table"));
+    }
+
+    #[test]
+    fn parse_impl_without_trait() {
+        assert_eq!(parse_impl("impl Point {"), None);
+        assert_eq!(parse_impl("impl<T> Wrapper<T> {"), None);
+        assert_eq!(parse_impl("implement for X"), None);
+        assert_eq!(parse_impl("fn impl_for() {}"), None);
+    }
+
+    #[test]
+    fn closing_angle_bracket_skips_arrows() {
+        assert_eq!(closing_angle_bracket("<T>"), Some(2));
+        assert_eq!(closing_angle_bracket("<F: Fn() -> T> X"), Some(13));
+        assert_eq!(closing_angle_bracket("<A<B>, C> X"), Some(8));
+        assert_eq!(closing_angle_bracket("<F: Fn() -> T"), None);
+    }
+}
@@ -0,0 +1,450 @@
+//#![allow(dead_code)]
+//#![allow(unused_variables)]
+
+//! Creates ctags/etags for a cargo project and all of its dependencies.
+//!
+//! Besides the 'rusty-tags' executable, the resolution of the dependency tree
+//! of a cargo project - the sources of its workspace members and dependencies -
+//! can be used without creating any tags by 'resolve_tree'.
+//!
+//! ```no_run
+//! extern crate rusty_tags;
+//!
+//! use rusty_tags::{resolve_tree, Config};
+//!
+//! # fn main() {
+//! let config = Config::from_args(&["rusty-tags", "vi", "--start-dir", "/path/to/project"]).unwrap();
+//! let dep_tree = resolve_tree(&config).unwrap();
+//! for source in dep_tree.all_sources() {
+//!     println!("{}: {}", source.name, source.dir.display());
+//! }
+//! # }
+//! ```
+
+extern crate toml;
+extern crate tempfile;
+extern crate num_cpus;
+extern crate scoped_threadpool;
+extern crate serde;
+extern crate serde_json;
+extern crate fnv;
+extern crate semver;
+extern crate dirs as extern_dirs;
+extern crate regex;
+extern crate globset;
+extern crate shell_words;
+extern crate sha2;
+
+#[cfg(unix)]
+extern crate libc;
+
+#[macro_use]
+extern crate serde_derive;
+
+#[macro_use]
+extern crate clap;
+
+#[macro_use]
+extern crate lazy_static;
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::env;
+use std::time::Instant;
+
+use tempfile::NamedTempFile;
+
+use dependencies::{dependency_tree, path_workspace_dirs, resolve_manifest_paths};
+use tags::{update_tags, create_tags, create_tags_with_args, move_tags, ensure_output_dir};
+use files::prelude_modules;
+use types::SourceLock;
+use dirs::network_file_system;
+use output::Location;
+use metrics::Metrics;
+
+#[macro_use]
+mod output;
+
+mod rt_result;
+mod dependencies;
+mod dirs;
+mod tags;
+mod types;
+mod config;
+mod impls;
+mod files;
+mod cfg;
+mod compression;
+mod cache;
+mod doc_tests;
+mod serve;
+mod components;
+mod verify;
+mod makefile;
+mod progress;
+mod traits;
+mod metrics;
+
+pub use rt_result::{RtResult, RtErr};
+pub use config::Config;
+pub use types::{DepTree, Source, SourceId};
+
+/// Runs 'rusty-tags' with the arguments of the command line.
+#[doc(hidden)]
+pub fn execute() -> RtResult<()> {
+    let config = Config::from_command_args()?;
+    if ! config.local_cache {
+        if let Some(fs_name) = network_file_system(&config.cache_dir) {
+            warning!(config, Location::File(&config.cache_dir, None),
+                     "the cache directory is on a network file system ({}), which might be slow \
+                      and have unreliable file locking. Consider using '--local-cache'.", fs_name);
+        }
+    }
+
+    update_all_tags(&config)?;
+    Ok(())
+}
+
+fn update_all_tags(config: &Config) -> RtResult<()> {
+    let start = Instant::now();
+    if config.cache_list {
+        return cache::list_cache(config, &resolve_tree(config)?);
+    }
+
+    if let Some(ref makefile) = config.emit_makefile {
+        return makefile::emit_makefile(config, &resolve_tree(config)?, makefile);
+    }
+
+    if let Some(ref git_ref) = config.since {
+        let metadatas = fetch_metadatas(config)?;
+        let path_metadatas = fetch_path_workspace_metadatas(config, &metadatas)?;
+        let dep_tree = dependency_tree(config, &metadatas, &path_metadatas)?;
+        return update_changed_files_tags(config, &metadatas[0], &dep_tree, git_ref);
+    }
+
+    let mut source_locks = Vec::new();
+    let dep_tree = {
+        let mut dep_tree = resolve_tree(config)?;
+        update_std_lib_tags(&config)?;
+        components::update_component_tags(&config)?;
+
+        let unlocked_root_ids: Vec<_> = {
+            let mut unlocked_roots = Vec::new();
+            for source in dep_tree.roots() {
+                match source.lock(&config.tags_spec)? {
+                    SourceLock::AlreadyLocked { ref path } => {
+                        warning!(config, Location::Crate(&source.name),
+                                 "Already creating tags, if this isn't the case remove the lock file '{}'", path.display());
+                        continue;
+                    }
+
+                    sl@SourceLock::Locked { .. } => {
+                        source_locks.push(sl);
+                        unlocked_roots.push(source);
+                    }
+                }
+            }
+
+            unlocked_roots.iter().map(|r| r.id).collect()
+        };
+
+        if unlocked_root_ids.is_empty() {
+            return Ok(());
+        }
+
+        dep_tree.set_roots(unlocked_root_ids);
+        dep_tree
+    };
+
+    let mut tags_metrics = Metrics::default();
+    let result = update_tags(&config, &dep_tree, &mut tags_metrics);
+    if let Some(ref metrics_file) = config.emit_metrics {
+        metrics::emit_metrics(config, &dep_tree, &tags_metrics, start.elapsed(), result.is_ok(), metrics_file)?;
+    }
+
+    result?;
+
+    if let Some(ref impls_file) = config.emit_impls {
+        impls::emit_impls(config, &dep_tree, impls_file)?;
+    }
+
+    if let Some(ref hierarchy_file) = config.emit_trait_hierarchy {
+        traits::emit_trait_hierarchy(config, &dep_tree, hierarchy_file)?;
+    }
+
+    // the locks of 'source_locks' are held while serving
+    if config.serve_stdin {
+        serve::serve_stdin(config, &dep_tree)?;
+    } else if config.cargo_messages {
+        serve::serve_cargo_messages(config, &dep_tree)?;
+    }
+
+    Ok(())
+}
+
+/// Resolves the dependency tree of the cargo project and of the additional workspaces
+/// by the output of 'cargo metadata', without creating any tags. The sources of the
+/// tree are fetched by cargo if they're missing.
+pub fn resolve_tree(config: &Config) -> RtResult<DepTree> {
+    let metadatas = fetch_metadatas(config)?;
+    let path_metadatas = fetch_path_workspace_metadatas(config, &metadatas)?;
+    dependency_tree(config, &metadatas, &path_metadatas)
+}
+
+/// The 'cargo metadata' outputs of the start directory and of the additional workspaces.
+fn fetch_metadatas(config: &Config) -> RtResult<Vec<serde_json::Value>> {
+    let mut metadatas = vec![fetch_source_and_metadata(config, &config.start_dir)?];
+    for workspace_dir in &config.workspace_dirs {
+        metadatas.push(fetch_source_and_metadata(config, workspace_dir)?);
+    }
+
+    Ok(metadatas)
+}
+
+/// The 'cargo metadata' outputs of the workspaces of the path dependencies outside of
+/// the workspaces of 'metadatas' for '--follow-path-workspaces', which are followed
+/// recursively into the workspaces of their path dependencies.
+fn fetch_path_workspace_metadatas(config: &Config, metadatas: &[serde_json::Value]) -> RtResult<Vec<serde_json::Value>> {
+    let mut path_metadatas = Vec::new();
+    if ! config.follow_path_workspaces {
+        return Ok(path_metadatas);
+    }
+
+    let mut known_dirs: Vec<PathBuf> = metadatas.iter()
+        .filter_map(|m| m.get("workspace_root").and_then(serde_json::Value::as_str).map(PathBuf::from))
+        .collect();
+
+    let mut pending_dirs = Vec::new();
+    for metadata in metadatas {
+        pending_dirs.extend(path_workspace_dirs(config, metadata)?);
+    }
+
+    while let Some(workspace_dir) = pending_dirs.pop() {
+        if known_dirs.contains(&workspace_dir) {
+            continue;
+        }
+
+        let metadata = fetch_source_and_metadata(config, &workspace_dir)?;
+        pending_dirs.extend(path_workspace_dirs(config, &metadata)?);
+        known_dirs.push(workspace_dir);
+        path_metadatas.push(metadata);
+    }
+
+    Ok(path_metadatas)
+}
+
+fn fetch_source_and_metadata(config: &Config, workspace_dir: &Path) -> RtResult<serde_json::Value> {
+    info!(config, "Fetching source and metadata ...");
+    verbose!(config, "   of workspace at '{}'", workspace_dir.display());
+
+    env::set_current_dir(&config.start_dir)?;
+
+    let mut cmd = Command::new("cargo");
+    cmd.current_dir(workspace_dir);
+    cmd.arg("metadata");
+    cmd.arg(format!("--format-version={}", config.metadata_format_version));
+    for cargo_config in &config.cargo_configs {
+        cmd.arg("--config").arg(cargo_config);
+    }
+
+    let output = cmd.output()
+        .map_err(|err| format!("'cargo' execution failed: {}\nIs 'cargo' correctly installed?", err))?;
+
+    if ! output.status.success() {
+        let mut msg = String::from_utf8_lossy(&output.stderr).into_owned();
+        if msg.is_empty() {
+            msg = String::from_utf8_lossy(&output.stdout).into_owned();
+        }
+
+        return Err(msg.into());
+    }
+
+    let mut metadata = serde_json::from_str(&String::from_utf8_lossy(&output.stdout))?;
+    validate_metadata(config, &metadata)?;
+    resolve_manifest_paths(&mut metadata, workspace_dir)?;
+    Ok(metadata)
+}
+
+type JsonCheck = fn(&serde_json::Value) -> bool;
+
+/// Checks that the metadata of cargo - of any format version - has the
+/// fields which rusty-tags reads, so that a changed format fails clearly.
+fn validate_metadata(config: &Config, metadata: &serde_json::Value) -> RtResult<()> {
+    let fields: [(&str, JsonCheck); 4] = [
+        ("packages", serde_json::Value::is_array),
+        ("workspace_members", serde_json::Value::is_array),
+        ("workspace_root", serde_json::Value::is_string),
+        ("resolve", serde_json::Value::is_object)
+    ];
+
+    for &(field, is_valid) in &fields {
+        if ! metadata.get(field).map(is_valid).unwrap_or(false) {
+            return Err(format!("Missing or invalid field '{}' in the output of 'cargo metadata' with the format version {}!",
+                               field, config.metadata_format_version).into());
+        }
+    }
+
+    if let Some(version) = metadata.get("version").and_then(serde_json::Value::as_u64) {
+        if version != u64::from(config.metadata_format_version) {
+            warning!(config, Location::Crate("cargo metadata"),
+                     "returned metadata of the format version {} instead of {}, the tags might be incomplete",
+                     version, config.metadata_format_version);
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates tags only for the source files of the workspace members that changed since
+/// 'git_ref' and writes them to '<tags file>.since' in the workspace root.
+fn update_changed_files_tags(config: &Config, metadata: &serde_json::Value, dep_tree: &DepTree, git_ref: &str) -> RtResult<()> {
+    let workspace_root = metadata.get("workspace_root")
+        .and_then(serde_json::Value::as_str)
+        .map(PathBuf::from)
+        .ok_or("Couldn't find 'workspace_root' in the metadata of cargo!")?;
+
+    info!(config, "Finding files changed since '{}' ...", git_ref);
+
+    let git_root = PathBuf::from(git_output(&workspace_root, &["rev-parse", "--show-toplevel"])?.trim());
+    let changed_files: Vec<PathBuf> = git_output(&workspace_root, &["diff", "--name-only", git_ref])?
+        .lines()
+        .map(|file| git_root.join(file))
+        .filter(|file| file.extension() == Some("rs".as_ref()) && file.is_file())
+        .filter(|file| dep_tree.roots().any(|root| file.starts_with(&root.dir)))
+        .collect();
+
+    if changed_files.is_empty() {
+        info!(config, "No source files of the workspace members changed since '{}'", git_ref);
+        return Ok(());
+    }
+
+    verbose!(config, "Found changed files: {:?}", changed_files);
+
+    let tags_file = workspace_root.join(format!("{}.since", config.tags_spec.file_name()));
+    info!(config, "Creating tags for {} changed files ...", changed_files.len());
+
+    ensure_output_dir(config, &tags_file)?;
+    let tmp_tags = NamedTempFile::new_in(&workspace_root)?;
+    create_tags(config, &changed_files, tmp_tags.path())?;
+    move_tags(config, tmp_tags.path(), &tags_file)?;
+
+    Ok(())
+}
+
+fn git_output(dir: &Path, args: &[&str]) -> RtResult<String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .map_err(|err| format!("'git' execution failed: {}\nIs 'git' correctly installed?", err))?;
+
+    if ! output.status.success() {
+        return Err(format!("'git {}' failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr)).into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn update_std_lib_tags(config: &Config) -> RtResult<()> {
+    let src_path_str = env::var("RUST_SRC_PATH");
+    if ! src_path_str.is_ok() {
+        return Ok(());
+    }
+
+    let src_path_str = src_path_str.unwrap();
+    let src_path = Path::new(&src_path_str);
+    if ! src_path.is_dir() {
+        return Err(format!("Missing rust source code at '{}'!", src_path.display()).into());
+    }
+
+    let output_path = match config.output_dir_std {
+        Some(ref path_buf) => path_buf.as_path(),
+        None => src_path,
+    };
+    let std_lib_tags = output_path.join(config.tags_spec.file_name());
+    if std_lib_tags.is_file() && ! config.force_recreate {
+        return Ok(());
+    }
+
+    let possible_src_dirs = [
+        // rustc >= 1.47.0
+        "alloc",
+        "core",
+        "panic_abort",
+        "panic_unwind",
+        "proc_macro",
+        "profiler_builtins",
+        "rtstartup",
+        "std",
+        "stdarch",
+        "term",
+        "test",
+        "unwind",
+
+        // rustc < 1.47.0
+        "liballoc",
+        "libarena",
+        "libbacktrace",
+        "libcollections",
+        "libcore",
+        "libflate",
+        "libfmt_macros",
+        "libgetopts",
+        "libgraphviz",
+        "liblog",
+        "librand",
+        "librbml",
+        "libserialize",
+        "libstd",
+        "libsyntax",
+        "libterm"
+    ];
+
+    let mut src_dirs = Vec::new();
+    for dir in &possible_src_dirs {
+        let src_dir = src_path.join(&dir);
+        if src_dir.is_dir() {
+            src_dirs.push(src_dir);
+        }
+    }
+
+    if src_dirs.is_empty() {
+        return Err(format!(r#"
+No source directories found for standard library source at $RUST_SRC_PATH:
+    '{}'
+
+Please set the standard library source path depending on your rustc version.
+
+For rustc >= 1.47.0:
+    $ export RUST_SRC_PATH=$(rustc --print sysroot)/lib/rustlib/src/rust/library/
+
+For rustc < 1.47.0:
+    $ export RUST_SRC_PATH=$(rustc --print sysroot)/lib/rustlib/src/rust/src/"#, src_path.display()).into());
+    }
+
+    ensure_output_dir(config, &std_lib_tags)?;
+    info!(config, "Creating tags for the standard library ...");
+
+    // the prelude modules only reexport the items of the other modules
+    let mut excluded_paths = Vec::new();
+    if config.omit_std_prelude {
+        for dir in &src_dirs {
+            excluded_paths.extend(prelude_modules(dir)?);
+        }
+
+        verbose!(config, "\nOmitting the prelude modules of the standard library:");
+        for path in &excluded_paths {
+            verbose!(config, "   {}", path.display());
+        }
+    }
+
+    let ctags_args: Vec<String> = excluded_paths.iter()
+        .map(|p| format!("--exclude={}", p.display()))
+        .collect();
+
+    let tmp_std_lib_tags = NamedTempFile::new_in(&output_path)?;
+    create_tags_with_args(config, &src_dirs, &excluded_paths, &ctags_args, tmp_std_lib_tags.path())?;
+    move_tags(config, tmp_std_lib_tags.path(), &std_lib_tags)?;
+
+    Ok(())
+}
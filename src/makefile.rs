@@ -0,0 +1,147 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use rt_result::RtResult;
+use types::{DepTree, Source, TagsKind, CacheCompression};
+use config::Config;
+use files::{foreign_dirs, is_cargo_target_dir};
+use tags::find_reexported_crates;
+
+/// Writes a Makefile into 'makefile', which creates the tags of 'dep_tree' with make
+/// instead of rusty-tags: every source gets a target for its cached tags file, which
+/// depends on its rust source files, and every root a target for its tags file, which
+/// depends on the cached tags files of the root and of its dependencies. The default
+/// target 'all' builds the tags files of all roots.
+///
+/// The Makefile only contains the creation of the tags by ctags and their merging,
+/// options of rusty-tags which post-process the tags aren't applied.
+pub fn emit_makefile(config: &Config, dep_tree: &DepTree, makefile: &Path) -> RtResult<()> {
+    if config.cache_compression != CacheCompression::None {
+        return Err("The option '--emit-makefile' doesn't support a compressed cache by '--cache-compression'!".into());
+    }
+
+    let mut sources: Vec<&Source> = dep_tree.all_sources().collect();
+    sources.sort_by(|a, b| (b.max_depth, &a.name, &a.version).cmp(&(a.max_depth, &b.name, &b.version)));
+
+    let mut writer = BufWriter::new(File::create(makefile)?);
+    writeln!(writer, "# Generated by rusty-tags with '--emit-makefile', run e.g. 'make -j -f {}'", makefile.display())?;
+    writeln!(writer)?;
+
+    let ctags_cmd = config.tags_spec.ctags_command();
+    let mut ctags = shell_word(&ctags_cmd.get_program().to_string_lossy());
+    for arg in ctags_cmd.get_args() {
+        ctags.push(' ');
+        ctags.push_str(&shell_word(&arg.to_string_lossy()));
+    }
+
+    writeln!(writer, "CTAGS = {}", ctags)?;
+    writeln!(writer)?;
+
+    let roots: Vec<&Source> = dep_tree.roots().collect();
+    let root_tags: Vec<String> = roots.iter().map(|r| make_path(&r.tags_file)).collect();
+    writeln!(writer, ".PHONY: all")?;
+    writeln!(writer, "all: {}", root_tags.join(" "))?;
+
+    for (idx, source) in sources.iter().enumerate() {
+        writeln!(writer)?;
+        writeln!(writer, "# {} {}", source.name, source.version)?;
+
+        // the source files are searched by make, so that new and removed files are considered
+        let mut excluded_dirs = foreign_dirs(&source.dir)?;
+        if config.index_target_dir(&source.name) {
+            excluded_dirs.retain(|d| ! is_cargo_target_dir(d));
+        }
+
+        let mut find = format!("find {}", shell_path(&source.dir));
+        if ! excluded_dirs.is_empty() {
+            let prunes: Vec<String> = excluded_dirs.iter().map(|d| format!("-path {}", shell_path(d))).collect();
+            find.push_str(&format!(" \\( {} \\) -prune -o", prunes.join(" -o ")));
+        }
+
+        writeln!(writer, "SOURCE_FILES_{} := $(shell {} -name '*.rs' -print)", idx, find)?;
+
+        let reexported_crates = find_reexported_crates(&source.dir)?;
+        let reexported_tags: Vec<&Path> = dep_tree.dependencies(source)
+            .filter(|d| reexported_crates.contains(&d.name))
+            .map(|d| d.cached_tags_file.as_path())
+            .collect();
+
+        let mut ctags_args: String = excluded_dirs.iter()
+            .map(|d| format!(" {}", shell_word(&format!("--exclude={}", d.display()))))
+            .collect();
+
+        if config.compact_deps && ! source.is_root && config.tags_spec.kind == TagsKind::Vi {
+            ctags_args.push_str(" --excmd=number");
+        }
+
+        let cached_tags = make_path(&source.cached_tags_file);
+        writeln!(writer, "{}: $(SOURCE_FILES_{}){}", cached_tags, idx, prerequisites(&reexported_tags))?;
+        if reexported_tags.is_empty() {
+            writeln!(writer, "\t$(CTAGS){} -o $@ {}", ctags_args, shell_path(&source.dir))?;
+        } else {
+            writeln!(writer, "\t$(CTAGS){} -o $@.src {}", ctags_args, shell_path(&source.dir))?;
+            writeln!(writer, "\t{}", merge_recipe(config, "$@.src", &reexported_tags))?;
+            writeln!(writer, "\trm -f $@.src")?;
+        }
+
+        if source.is_root {
+            let dep_tags: Vec<&Path> = dep_tree.dependencies(source)
+                .map(|d| d.cached_tags_file.as_path())
+                .collect();
+
+            writeln!(writer)?;
+            writeln!(writer, "{}: {}{}", make_path(&source.tags_file), cached_tags, prerequisites(&dep_tags))?;
+            writeln!(writer, "\t{}", merge_recipe(config, "$<", &dep_tags))?;
+        }
+    }
+
+    writer.flush()?;
+    verbose!(config, "\nWrote Makefile for {} sources to '{}'", sources.len(), makefile.display());
+    Ok(())
+}
+
+/// The recipe merging the tags file 'tags' and the tags files 'dep_tags' into the target '$@',
+/// like 'merge_tags': the vi tags are sorted by bytes and the emacs tags include the others.
+fn merge_recipe(config: &Config, tags: &str, dep_tags: &[&Path]) -> String {
+    match config.tags_spec.kind {
+        TagsKind::Vi => {
+            let dep_tags: Vec<String> = dep_tags.iter().map(|d| shell_path(d)).collect();
+            format!("LC_ALL=C sort -u -o $@.tmp {} {} && mv $@.tmp $@", tags, dep_tags.join(" "))
+        }
+
+        TagsKind::Emacs => {
+            let mut recipe = format!("cp {} $@", tags);
+            for dep in dep_tags {
+                recipe.push_str(&format!(" && echo {} >> $@", shell_word(&format!("{},include", dep.display()))));
+            }
+
+            recipe
+        }
+    }
+}
+
+fn prerequisites(paths: &[&Path]) -> String {
+    paths.iter().map(|p| format!(" {}", make_path(p))).collect()
+}
+
+/// A path as make target or prerequisite.
+fn make_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .replace('$', "$$")
+        .replace(' ', "\\ ")
+}
+
+/// A path as argument of a shell command in a recipe.
+fn shell_path(path: &Path) -> String {
+    shell_word(&path.to_string_lossy())
+}
+
+fn shell_word(word: &str) -> String {
+    let word = word.replace('$', "$$");
+    if ! word.is_empty() && word.chars().all(|c| c.is_alphanumeric() || "-_=./:,+".contains(c)) {
+        word
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tempfile::NamedTempFile;
+
+use rt_result::RtResult;
+use types::DepTree;
+use config::Config;
+use tags::read_tag_entries;
+
+/// The counts of a tags update.
+#[derive(Default)]
+pub struct Metrics {
+    /// the sources whose tags were recreated
+    pub num_updated: usize,
+
+    /// the sources whose tags couldn't be created
+    pub num_failed: usize
+}
+
+/// Writes the metrics of the tags update of 'dep_tree' in the Prometheus text
+/// format into 'metrics_file', e.g. for the textfile collector of the node exporter.
+///
+/// The file is written atomically, so that a collector never reads a partial file.
+pub fn emit_metrics(config: &Config, dep_tree: &DepTree, metrics: &Metrics, duration: Duration,
+                    success: bool, metrics_file: &Path) -> RtResult<()> {
+    info!(config, "Writing metrics to '{}' ...", metrics_file.display());
+
+    let num_sources = dep_tree.all_sources().count();
+    let num_cached = num_sources.saturating_sub(metrics.num_updated);
+    let cache_hit_ratio = if num_sources > 0 { num_cached as f64 / num_sources as f64 } else { 1. };
+
+    let mut num_tags = 0;
+    for root in dep_tree.roots() {
+        if root.tags_file.is_file() {
+            num_tags += read_tag_entries(config, &root.tags_file)?.len();
+        }
+    }
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+        let _ = writeln!(text, "# HELP rusty_tags_{} {}", name, help);
+        let _ = writeln!(text, "# TYPE rusty_tags_{} {}", name, kind);
+        let _ = writeln!(text, "rusty_tags_{} {}", name, value);
+    };
+
+    metric("sources", "gauge", "Number of sources in the dependency tree.", num_sources.to_string());
+    metric("sources_updated", "gauge", "Number of sources whose tags were recreated.", metrics.num_updated.to_string());
+    metric("cache_hit_ratio", "gauge", "Ratio of the sources whose cached tags were reused.", cache_hit_ratio.to_string());
+    metric("tags", "gauge", "Number of tags in the tags files of the workspace members.", num_tags.to_string());
+    metric("failures", "gauge", "Number of sources whose tags couldn't be created.", metrics.num_failed.to_string());
+    metric("duration_seconds", "gauge", "Duration of the tags update in seconds.", duration.as_secs_f64().to_string());
+    metric("success", "gauge", "If the tags update succeeded.", (success as u8).to_string());
+    metric("last_run_timestamp_seconds", "gauge", "Unix time of the end of the tags update.", timestamp.to_string());
+
+    let metrics_dir = match metrics_file.parent() {
+        Some(dir) if ! dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+    };
+
+    fs::create_dir_all(metrics_dir)?;
+    let mut tmp_file = NamedTempFile::new_in(metrics_dir)?;
+    tmp_file.write_all(text.as_bytes())?;
+
+    // the temporary file is only readable by the owner, but
+    // the collector usually runs as another user
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(tmp_file.path(), fs::Permissions::from_mode(0o644))?;
+    }
+
+    tmp_file.persist(metrics_file)
+        .map_err(|err| format!("Couldn't write metrics file '{}': {}", metrics_file.display(), err))?;
+
+    Ok(())
+}
@@ -27,3 +27,44 @@ macro_rules! verbose {
         }
     }};
 }
+
+/// Prints a warning about `$location` - a `output::Location` - in the format given
+/// by `--warning-format`, e.g. 'path:line: message' for editor quickfix parsing.
+macro_rules! warning {
+    ($config:ident, $location:expr, $fmt:expr) => {{
+        if ! $config.quiet {
+            ::output::print_warning($config.warning_format, &$location, &format!($fmt));
+        }
+    }};
+
+    ($config:ident, $location:expr, $fmt:expr, $($arg:tt)*) => {{
+        if ! $config.quiet {
+            ::output::print_warning($config.warning_format, &$location, &format!($fmt, $($arg)*));
+        }
+    }};
+}
+
+use std::path::Path;
+
+use types::WarningFormat;
+
+/// What a warning is about.
+pub enum Location<'a> {
+    /// a file and optionally a line in it
+    File(&'a Path, Option<usize>),
+
+    /// a crate by its name
+    Crate(&'a str)
+}
+
+pub fn print_warning(format: WarningFormat, location: &Location, msg: &str) {
+    match (format, location) {
+        (WarningFormat::Text, &Location::File(path, Some(line))) => println!("Warning: {}:{}: {}", path.display(), line, msg),
+        (WarningFormat::Text, &Location::File(path, None))       => println!("Warning: {}: {}", path.display(), msg),
+        (WarningFormat::Text, &Location::Crate(name))            => println!("Warning: {}: {}", name, msg),
+
+        // quickfix parsing needs a line number for file locations
+        (WarningFormat::Quickfix, &Location::File(path, line))   => println!("{}:{}: {}", path.display(), line.unwrap_or(1), msg),
+        (WarningFormat::Quickfix, &Location::Crate(name))        => println!("{}: {}", name, msg)
+    }
+}
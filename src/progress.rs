@@ -0,0 +1,143 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use config::Config;
+
+/// The progress of the sources processed by the threads of the thread pool, rendered
+/// to stderr by its own thread: on a terminal as one line, which is updated in place,
+/// with the number of completed sources and the names of the sources in progress,
+/// otherwise as periodic summary lines.
+pub struct Progress {
+    shared: Option<Arc<Shared>>,
+    renderer: Option<JoinHandle<()>>
+}
+
+struct Shared {
+    /// what is done with the sources, e.g. "Creating tags"
+    title: String,
+
+    total: usize,
+    is_terminal: bool,
+    state: Mutex<State>,
+    finished: Condvar
+}
+
+#[derive(Default)]
+struct State {
+    done: usize,
+
+    /// the names of the sources in progress, in their starting order
+    in_progress: Vec<String>,
+
+    finished: bool
+}
+
+/// The maximal width of the progress line on a terminal.
+const LINE_WIDTH: usize = 79;
+
+impl Progress {
+    /// Starts the rendering of the progress of 'total' sources with '--progress',
+    /// otherwise the returned progress doesn't render anything.
+    pub fn start(config: &Config, title: &str, total: usize) -> Progress {
+        if ! config.progress || total == 0 {
+            return Progress { shared: None, renderer: None };
+        }
+
+        let shared = Arc::new(Shared {
+            title: title.to_string(),
+            total,
+            is_terminal: io::stderr().is_terminal(),
+            state: Mutex::new(State::default()),
+            finished: Condvar::new()
+        });
+
+        let renderer = {
+            let shared = shared.clone();
+            thread::spawn(move || shared.render())
+        };
+
+        Progress { shared: Some(shared), renderer: Some(renderer) }
+    }
+
+    /// Called by a thread when it starts processing the source 'name'.
+    pub fn begin(&self, name: &str) {
+        if let Some(ref shared) = self.shared {
+            shared.state.lock().unwrap().in_progress.push(name.to_string());
+        }
+    }
+
+    /// Called by a thread when it finished processing the source 'name'.
+    pub fn end(&self, name: &str) {
+        if let Some(ref shared) = self.shared {
+            let mut state = shared.state.lock().unwrap();
+            if let Some(idx) = state.in_progress.iter().position(|n| n == name) {
+                state.in_progress.remove(idx);
+            }
+
+            state.done += 1;
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(ref shared) = self.shared {
+            shared.state.lock().unwrap().finished = true;
+            shared.finished.notify_all();
+        }
+
+        if let Some(renderer) = self.renderer.take() {
+            let _ = renderer.join();
+        }
+    }
+}
+
+impl Shared {
+    fn render(&self) {
+        // summary lines are only written if the progress changed
+        let interval = if self.is_terminal { Duration::from_millis(100) } else { Duration::from_secs(2) };
+        let mut last_done = None;
+
+        let mut state = self.state.lock().unwrap();
+        while ! state.finished {
+            state = self.finished.wait_timeout(state, interval).unwrap().0;
+            if state.finished {
+                break;
+            }
+
+            if self.is_terminal {
+                let line = self.progress_line(&state);
+                eprint!("\r{}\x1b[K", line);
+            } else if last_done != Some(state.done) {
+                eprintln!("{}", self.progress_line(&state));
+                last_done = Some(state.done);
+            }
+        }
+
+        let summary = format!("{}: {}/{} sources done", self.title, state.done, self.total);
+        if self.is_terminal {
+            eprintln!("\r{}\x1b[K", summary);
+        } else {
+            eprintln!("{}", summary);
+        }
+
+        let _ = io::stderr().flush();
+    }
+
+    fn progress_line(&self, state: &State) -> String {
+        let mut line = format!("{}: {}/{} sources done", self.title, state.done, self.total);
+        if ! state.in_progress.is_empty() {
+            line.push_str(&format!(", in progress: {}", state.in_progress.join(", ")));
+        }
+
+        // a longer line would wrap and break the update in place
+        if self.is_terminal && line.chars().count() > LINE_WIDTH {
+            line = line.chars().take(LINE_WIDTH - 3).collect();
+            line.push_str("...");
+        }
+
+        line
+    }
+}
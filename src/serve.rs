@@ -0,0 +1,192 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use serde_json::{self, Value as JsonValue};
+use fnv::FnvHashSet;
+use tempfile::NamedTempFile;
+
+use rt_result::RtResult;
+use types::{DepTree, SourceId};
+use config::Config;
+use tags::{create_tags, depth_band, is_skipped_file, splice_tags, update_sources_tags, add_provenance_fields, post_process_final_tags, run_validate_command, move_tags};
+use files::{relative_path, relative_to_roots};
+use metrics::Metrics;
+
+/// Reads 'changed <path>' lines from stdin, recreates the tags of the changed
+/// source file, splices them into the tags of the workspace members and
+/// answers with 'done <path>' on stdout, or with 'error <path>: <message>'.
+pub fn serve_stdin(config: &Config, dep_tree: &DepTree) -> RtResult<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let reply = if let Some(path) = line.strip_prefix("changed ") {
+            let path = path.trim();
+            match update_changed_file(config, dep_tree, Path::new(path)) {
+                Ok(())   => format!("done {}", path),
+                Err(err) => format!("error {}: {}", path, err.to_string().trim())
+            }
+        } else {
+            format!("error unknown command: {}", line)
+        };
+
+        let mut stdout = stdout.lock();
+        writeln!(stdout, "{}", reply)?;
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Reads the JSON messages of 'cargo build --message-format=json' from stdin and
+/// recreates the tags of each workspace member when its compilation has finished.
+pub fn serve_cargo_messages(config: &Config, dep_tree: &DepTree) -> RtResult<()> {
+    // the members with updated tags since the start of the current build,
+    // an artifact is reported for each compiled target of a member
+    let mut updated_ids = FnvHashSet::<SourceId>::default();
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let message: JsonValue = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(_) => {
+                verbose!(config, "Ignoring non JSON line from cargo: {}", line);
+                continue;
+            }
+        };
+
+        match message.get("reason").and_then(JsonValue::as_str) {
+            Some("compiler-artifact") => {}
+            Some("build-finished") => {
+                updated_ids.clear();
+                continue;
+            }
+            _ => continue
+        }
+
+        // artifacts of unchanged crates are reported as fresh
+        if message.get("fresh").and_then(JsonValue::as_bool) == Some(true) {
+            continue;
+        }
+
+        let manifest_dir = match message.get("manifest_path").and_then(JsonValue::as_str) {
+            Some(path) => match Path::new(path).parent() {
+                Some(dir) => dir.to_path_buf(),
+                None => continue
+            },
+            None => continue
+        };
+
+        let member = dep_tree.roots()
+            .find(|root| root.tags_file.parent() == Some(manifest_dir.as_path()));
+
+        if let Some(member) = member {
+            if updated_ids.insert(member.id) {
+                info!(config, "Updating tags for {} after its compilation ...", member.name);
+                update_sources_tags(config, dep_tree, &[member], &mut Metrics::default())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn update_changed_file(config: &Config, dep_tree: &DepTree, path: &Path) -> RtResult<()> {
+    let src_file = config.start_dir.join(path);
+    if ! src_file.is_file() {
+        return Err(format!("Couldn't find source file '{}'", src_file.display()).into());
+    }
+
+    let owner = dep_tree.roots()
+        .filter(|root| src_file.starts_with(&root.dir))
+        .max_by_key(|root| root.dir.components().count())
+        .ok_or(format!("Source file '{}' isn't part of a workspace member", src_file.display()))?;
+
+    // the tags of a skipped file are only removed from the tags files
+    let file_tags = config.temp_file(&format!("changed.{}", config.tags_spec.file_extension()))?;
+    if is_skipped_file(config, owner, &src_file)? {
+        verbose!(config, "Removing the tags of the skipped file '{}'", src_file.display());
+    } else {
+        create_tags(config, &[&src_file], &file_tags)?;
+        if config.embed_provenance {
+            add_provenance_fields(config, owner, &file_tags)?;
+        }
+    }
+
+    // the tags files of the workspace members contain the cached tags of their direct
+    // dependencies, the depth tags files of '--split-by-depth' also of their indirect ones
+    let mut tags_files = vec![owner.cached_tags_file.clone()];
+    for root in dep_tree.roots() {
+        let band = depth_band(dep_tree, root, owner);
+        if band == Some("0") || band == Some("1") {
+            tags_files.push(root.tags_file.clone());
+        }
+
+        if config.split_by_depth {
+            if let Some(band) = band {
+                tags_files.push(root.depth_tags_file(band));
+            }
+        }
+    }
+
+    let filtered_dirs: Vec<&Path> = match config.module_filter {
+        Some(ref filter) if filter.is_in_crate(&owner.name) => vec![&owner.dir],
+        _ => Vec::new()
+    };
+
+    // the tags files are spliced into copies, which only replace them if the tags
+    // files of the members are accepted by '--validate-command', like for an update
+    // of all tags, otherwise all previous tags files are kept
+    let mut spliced_files = Vec::new();
+    for tags_file in &tags_files {
+        if ! tags_file.is_file() {
+            continue;
+        }
+
+        let tags_dir = tags_file.parent().unwrap_or(Path::new("."));
+        let spliced_tags = NamedTempFile::new_in(tags_dir)?;
+        fs::copy(tags_file, spliced_tags.path())?;
+
+        if *tags_file == owner.cached_tags_file {
+            splice_tags(config, &file_tags, &src_file, spliced_tags.path())?;
+            spliced_files.push((spliced_tags, tags_file));
+            continue;
+        }
+
+        // the final tags files contain the tags post-processed like by an update of all tags,
+        // so the paths of the file tags and of the spliced source file have to match them
+        let final_file_tags = config.temp_file(&format!("changed.final.{}", config.tags_spec.file_extension()))?;
+        fs::copy(&file_tags, &final_file_tags)?;
+        post_process_final_tags(config, &final_file_tags, &[&owner.dir], &filtered_dirs, tags_dir)?;
+
+        let mut final_src_file = if config.mixed_paths {
+            relative_path(tags_dir, &src_file)
+        } else {
+            src_file.clone()
+        };
+
+        if ! config.relative_roots.is_empty() {
+            final_src_file = relative_to_roots(&src_file, &config.relative_roots).unwrap_or(final_src_file);
+        }
+
+        splice_tags(config, &final_file_tags, &final_src_file, spliced_tags.path())?;
+        if dep_tree.roots().any(|r| r.tags_file == *tags_file) {
+            run_validate_command(config, spliced_tags.path(), tags_file)?;
+        }
+
+        spliced_files.push((spliced_tags, tags_file));
+    }
+
+    for (spliced_tags, tags_file) in &spliced_files {
+        move_tags(config, spliced_tags.path(), tags_file)?;
+    }
+
+    Ok(())
+}
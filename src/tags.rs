@@ -1,18 +1,25 @@
-use std::fs::{File, OpenOptions, copy, rename};
+use std::fs::{File, OpenOptions, copy, rename, create_dir_all};
 use std::io::{Read, Write, BufWriter};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
 
 use tempfile::NamedTempFile;
 use scoped_threadpool::Pool;
 use fnv::FnvHashSet;
+use serde_json;
 
 use rt_result::RtResult;
-use types::{TagsKind, SourceWithTmpTags, Sources, DepTree, unique_sources};
+use types::{TagsKind, TagsFormat, PseudoTags, Source, SourceId, SourceWithTmpTags, DepTree, unique_sources, contents_hash};
 use config::Config;
-use dirs::rusty_tags_cache_dir;
-
-/// Update the tags of all sources in 'dep_tree'
-pub fn update_tags(config: &Config, dep_tree: &DepTree) -> RtResult<()> {
+use doc_tests::extract_doc_tests;
+use files::{rust_files, module_files, cfg_test_files, inactive_cfg_files, oversized_files, foreign_dirs, is_cargo_target_dir, is_excluded, normalize_path, relative_path, relative_to_roots};
+use compression::{detect_compression, read_tags_file, move_compressed};
+use progress::Progress;
+use metrics::Metrics;
+
+/// Update the tags of all sources in 'dep_tree', the updated and failed sources are counted in 'metrics'
+pub fn update_tags(config: &Config, dep_tree: &DepTree, metrics: &mut Metrics) -> RtResult<()> {
     if ! config.quiet {
         let names: Vec<_> = dep_tree.roots().map(|r| &r.name).collect();
         let num_names = names.len();
@@ -27,18 +34,36 @@ pub fn update_tags(config: &Config, dep_tree: &DepTree) -> RtResult<()> {
         print!(" ...\n");
     }
 
-    let sources_to_update: Vec<_> = dep_tree.all_sources().filter(|s| {
-        s.needs_tags_update(config)
-    })
-    .collect();
+    let sources_to_update: Vec<_> = match config.impacted_by {
+        Some(ref crate_name) => impacted_sources(dep_tree, crate_name)?,
+        None => dep_tree.all_sources().filter(|s| {
+            s.needs_tags_update(config)
+        })
+        .collect()
+    };
+
+    update_sources_tags(config, dep_tree, &sources_to_update, metrics)?;
+
+    if config.split_tests {
+        update_test_tags(config, dep_tree)?;
+    }
+
+    if config.require_member_tags {
+        check_member_tags(config, dep_tree)?;
+    }
 
+    Ok(())
+}
+
+/// Update the tags of 'sources_to_update' and of all of their dependent (ancestor) sources.
+pub fn update_sources_tags(config: &Config, dep_tree: &DepTree, sources_to_update: &[&Source], metrics: &mut Metrics) -> RtResult<()> {
     // If a source with missing tags was detected (the 'sources_to_update' above), then all
     // dependent (ancestor) sources also have to be updated. The reason for the missing tags
     // might be a version change of the source - by changes in the 'Cargo.toml' - so all
     // dependent sources have to be rebuild to include the new version.
     let sources_to_update = {
-        let mut srcs = dep_tree.ancestors(&sources_to_update);
-        srcs.extend(&sources_to_update);
+        let mut srcs = dep_tree.ancestors(sources_to_update);
+        srcs.extend(sources_to_update);
         unique_sources(&mut srcs);
 
         // sort the sources by their depth in the dependency tree to ensure that
@@ -54,6 +79,11 @@ pub fn update_tags(config: &Config, dep_tree: &DepTree) -> RtResult<()> {
         srcs_with_tags
     };
 
+    // fail before the expensive tags creation if the tags files can't be written
+    for &SourceWithTmpTags { source, .. } in &sources_to_update {
+        ensure_output_dir(config, &source.tags_file)?;
+    }
+
     if config.verbose && ! sources_to_update.is_empty() {
         println!("\nCreating tags for sources:");
         for &SourceWithTmpTags { source, .. } in &sources_to_update {
@@ -61,53 +91,104 @@ pub fn update_tags(config: &Config, dep_tree: &DepTree) -> RtResult<()> {
         }
     }
 
-    let mut thread_pool = if config.num_threads > 1 {
-        Some(Pool::new(config.num_threads))
-    } else {
-        None
-    };
-
-    // Create the tags for each source in 'sources_to_update'. This creates
-    // only the tags of the source without considering the dependencies.
-    if let Some(ref mut thread_pool) = thread_pool {
-        thread_pool.scoped(|scoped| {
-            for &SourceWithTmpTags { ref source, ref tags_file, .. } in &sources_to_update {
-                scoped.execute(move || {
-                    create_tags(config, &[&source.dir], tags_file.as_path()).unwrap();
+    let failures = SourceFailures::new();
+    let result = create_and_merge_tags(config, dep_tree, &sources_to_update, &failures);
+    metrics.num_updated += sources_to_update.len();
+    metrics.num_failed += failures.len();
+    result?;
+    return failures.report(config, sources_to_update.len());
+
+    fn create_and_merge_tags(config: &Config, dep_tree: &DepTree, sources_to_update: &[SourceWithTmpTags],
+                             failures: &SourceFailures) -> RtResult<()> {
+        let mut thread_pool = if config.num_threads > 1 {
+            Some(Pool::new(config.num_threads))
+        } else {
+            None
+        };
+
+        // Create the tags for each source in 'sources_to_update'. This creates
+        // only the tags of the source without considering the dependencies.
+        {
+            let progress = Progress::start(config, "Creating tags", sources_to_update.len());
+            if let Some(ref mut thread_pool) = thread_pool {
+                let progress = &progress;
+                thread_pool.scoped(|scoped| {
+                    for &SourceWithTmpTags { ref source, ref tags_file, .. } in sources_to_update {
+                        scoped.execute(move || {
+                            progress.begin(&source.name);
+                            failures.record(source, create_source_tags(config, source, tags_file.as_path()));
+                            progress.end(&source.name);
+                        });
+                    }
                 });
+            } else {
+                for &SourceWithTmpTags { ref source, ref tags_file, .. } in sources_to_update {
+                    progress.begin(&source.name);
+                    failures.record(source, create_source_tags(config, source, tags_file.as_path()));
+                    progress.end(&source.name);
+                    failures.check(config)?;
+                }
             }
-        });
-    } else {
-        for &SourceWithTmpTags { ref source, ref tags_file, .. } in &sources_to_update {
-            create_tags(config, &[&source.dir], tags_file.as_path())?;
         }
-    }
 
-    // Creates the cacheable tags of each source in 'sources_to_update'. The cacheable
-    // tags contain the tags of the source and the tags of the public exported dependencies.
-    // Furthermore creates the final tags of each source in 'sources_to_update'. The
-    // final tags contain the tags of the source and of all direct dependencies.
-    if let Some(ref mut thread_pool) = thread_pool {
-        thread_pool.scoped(|scoped| {
-            for src in &sources_to_update {
-                scoped.execute(move || {
-                    let deps = dep_tree.dependencies(src.source);
-                    update_tags_internal(config, src, deps).unwrap();
+        failures.check(config)?;
+
+        // Creates the cacheable tags of each source in 'sources_to_update'. The cacheable
+        // tags contain the tags of the source and the tags of the public exported dependencies.
+        // Furthermore creates the final tags of each source in 'sources_to_update'. The
+        // final tags contain the tags of the source and of all direct dependencies.
+        //
+        // Sources whose tags couldn't be created are skipped, so that
+        // no cached tags get written for them and the next run retries them.
+        let failed_sources = failures.sources();
+        let sources_to_update: Vec<_> = sources_to_update.iter()
+            .filter(|s| ! failed_sources.contains(&s.source.id))
+            .collect();
+
+        // the source directories of the crate of '--module-filter', which might be in the
+        // final tags of any source by the cached tags of a reexporting dependency
+        let filtered_dirs: Vec<&Path> = match config.module_filter {
+            Some(ref filter) => dep_tree.all_sources()
+                .filter(|s| filter.is_in_crate(&s.name))
+                .map(|s| s.dir.as_path())
+                .collect(),
+
+            None => Vec::new()
+        };
+
+        {
+            let filtered_dirs = &filtered_dirs[..];
+            let progress = Progress::start(config, "Merging tags", sources_to_update.len());
+            if let Some(ref mut thread_pool) = thread_pool {
+                let progress = &progress;
+                thread_pool.scoped(|scoped| {
+                    for &src in &sources_to_update {
+                        scoped.execute(move || {
+                            progress.begin(&src.source.name);
+                            failures.record(src.source, update_tags_internal(config, src, dep_tree, filtered_dirs));
+                            progress.end(&src.source.name);
+                        });
+                    }
                 });
+            } else {
+                for &src in &sources_to_update {
+                    progress.begin(&src.source.name);
+                    failures.record(src.source, update_tags_internal(config, src, dep_tree, filtered_dirs));
+                    progress.end(&src.source.name);
+                    failures.check(config)?;
+                }
             }
-        });
-    } else {
-        for src in &sources_to_update {
-            let deps = dep_tree.dependencies(src.source);
-            update_tags_internal(config, src, deps)?;
         }
-    }
 
-    return Ok(());
+        failures.check(config)?;
+        Ok(())
+    }
 
-    fn update_tags_internal<'a>(config: &Config, source_with_tags: &SourceWithTmpTags<'a>, dependencies: Sources<'a>) -> RtResult<()> {
+    fn update_tags_internal<'a>(config: &Config, source_with_tags: &SourceWithTmpTags<'a>, dep_tree: &'a DepTree,
+                               filtered_dirs: &[&Path]) -> RtResult<()> {
         let source = source_with_tags.source;
         let tmp_src_tags = source_with_tags.tags_file.as_path();
+        let dependencies = dep_tree.dependencies(source);
 
         // create the cached tags file of 'source' which
         // might also contain the tags of dependencies if they're
@@ -138,14 +219,18 @@ pub fn update_tags(config: &Config, dep_tree: &DepTree) -> RtResult<()> {
                 })
                 .collect();
 
-            let tmp_cached_tags = NamedTempFile::new_in(rusty_tags_cache_dir()?)?;
+            let tmp_cached_tags = NamedTempFile::new_in(&config.cache_dir)?;
             if ! reexported_tags_files.is_empty() {
                 merge_tags(config, tmp_src_tags, &reexported_tags_files, tmp_cached_tags.path())?;
             } else {
                 copy_tags(config, tmp_src_tags, tmp_cached_tags.path())?;
             }
 
-            move_tags(config, tmp_cached_tags.path(), &source.cached_tags_file)?;
+            move_compressed(config, config.cache_compression, tmp_cached_tags.path(), &source.cached_tags_file)?;
+            write_ctags_version(config, source)?;
+            if source.is_path_dependency {
+                write_contents_hash(source)?;
+            }
         }
 
         // create the source tags file of 'source' by merging
@@ -170,25 +255,611 @@ pub fn update_tags(config: &Config, dep_tree: &DepTree) -> RtResult<()> {
                 copy_tags(config, tmp_src_tags, tmp_src_and_dep_tags.path())?;
             }
 
-            move_tags(config, tmp_src_and_dep_tags.path(), &source.tags_file)?;
+            let root_dirs: Vec<&Path> = Some(source).into_iter()
+                .chain(dependencies.clone())
+                .filter(|s| s.is_root)
+                .map(|s| s.dir.as_path())
+                .collect();
+
+            let validate = source.is_root;
+            finish_final_tags(config, tmp_src_and_dep_tags.path(), &root_dirs, filtered_dirs, validate, &source.tags_file)?;
+        }
+
+        if config.split_by_depth && source.is_root {
+            write_depth_tags(config, source, tmp_src_tags, dep_tree, filtered_dirs)?;
         }
 
         Ok(())
     }
 }
 
+/// applies the options post-processing the final tags to `tags_file`, whose tags of the roots
+/// are in `root_dirs`, and moves it to `final_tags_file`, `filtered_dirs` are the source
+/// directories of the crate of '--module-filter'. If `validate` is set, then the command of
+/// '--validate-command' has to accept `tags_file`, otherwise `final_tags_file` is kept.
+fn finish_final_tags(config: &Config, tags_file: &Path, root_dirs: &[&Path], filtered_dirs: &[&Path],
+                     validate: bool, final_tags_file: &Path) -> RtResult<()> {
+    let tags_dir = final_tags_file.parent().unwrap_or(Path::new("."));
+    post_process_final_tags(config, tags_file, root_dirs, filtered_dirs, tags_dir)?;
+
+    if validate {
+        run_validate_command(config, tags_file, final_tags_file)?;
+    }
+
+    move_tags(config, tags_file, final_tags_file)
+}
+
+/// applies the options post-processing the final tags to `tags_file` for a final tags file
+/// in `tags_dir`, which are '--module-filter', '--mixed-paths', '--relative-roots',
+/// '--format=minimal' and '--line-ending'. `tags_file` might also only contain the tags
+/// of a single source file, which '--serve-stdin' splices into the final tags files.
+pub fn post_process_final_tags(config: &Config, tags_file: &Path, root_dirs: &[&Path], filtered_dirs: &[&Path],
+                               tags_dir: &Path) -> RtResult<()> {
+    if let Some(ref module_filter) = config.module_filter {
+        filter_module_tags(tags_file, filtered_dirs, &module_filter.module_path)?;
+    }
+
+    if config.mixed_paths {
+        make_paths_relative(tags_file, root_dirs, tags_dir)?;
+    }
+
+    if ! config.relative_roots.is_empty() {
+        make_paths_relative_to_roots(tags_file, &config.relative_roots)?;
+    }
+
+    if config.tags_format == TagsFormat::Minimal {
+        strip_to_minimal(tags_file)?;
+    }
+
+    convert_line_endings(config, tags_file)
+}
+
+/// runs the command of '--validate-command' with the path of the new tags `tags_file` as last
+/// argument and its contents on stdin, if it fails, then the previous `final_tags_file` is kept
+pub fn run_validate_command(config: &Config, tags_file: &Path, final_tags_file: &Path) -> RtResult<()> {
+    let (program, args) = match config.validate_command.split_first() {
+        Some(cmd) => cmd,
+        None => return Ok(())
+    };
+
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+       .arg(tags_file)
+       .stdin(File::open(tags_file)?)
+       .env("RUSTY_TAGS_FINAL_FILE", final_tags_file);
+
+    verbose!(config, "\nRunning validate command for '{}':\n   {:?}", final_tags_file.display(), cmd);
+
+    let output = cmd.output()
+        .map_err(|err| format!("Execution of the validate command {:?} failed: {}", config.validate_command, err))?;
+
+    if ! output.status.success() {
+        let mut msg = format!("The validate command {:?} rejected the new tags for '{}' with {}, keeping the previous ones",
+                              config.validate_command, final_tags_file.display(), output.status);
+        if ! output.stderr.is_empty() {
+            msg.push_str(&format!(":\n{}", String::from_utf8_lossy(&output.stderr)));
+        }
+
+        return Err(msg.into());
+    }
+
+    Ok(())
+}
+
+/// writes the tags of the root `source` split by the depth of its dependencies for
+/// '--split-by-depth': its own tags `src_tags` beside of its tags file into '<tags file>.0',
+/// the tags of its direct dependencies into '<tags file>.1' and the tags of its indirect
+/// dependencies into '<tags file>.2plus'
+fn write_depth_tags(config: &Config, source: &Source, src_tags: &Path, dep_tree: &DepTree,
+                    filtered_dirs: &[&Path]) -> RtResult<()> {
+    let deps_in_band = |band| -> Vec<&Source> {
+        dep_tree.descendants(source)
+            .into_iter()
+            .filter(|d| d.id != source.id && depth_band(dep_tree, source, d) == Some(band))
+            .collect()
+    };
+
+    // only the pseudo tags of the source, into which the tags of the dependencies of a band are merged
+    let pseudo_tags = NamedTempFile::new_in(&source.dir)?;
+    if config.tags_spec.kind == TagsKind::Vi {
+        let mut writer = BufWriter::new(File::create(pseudo_tags.path())?);
+        for line in read_file(src_tags)?.lines().take_while(|l| l.starts_with('!')) {
+            writeln!(writer, "{}", line)?;
+        }
+    }
+
+    let root_dirs: Vec<&Path> = dep_tree.roots().map(|r| r.dir.as_path()).collect();
+    let bands = [("0", src_tags, Vec::new()), ("1", pseudo_tags.path(), deps_in_band("1")), ("2plus", pseudo_tags.path(), deps_in_band("2plus"))];
+    for &(band, tags, ref deps) in &bands {
+        let dep_tags_files: Vec<&Path> = deps.iter()
+            .map(|d| d.cached_tags_file.as_path())
+            .filter(|f| f.is_file())
+            .collect();
+
+        let tmp_band_tags = NamedTempFile::new_in(&source.dir)?;
+        if ! dep_tags_files.is_empty() {
+            merge_tags(config, tags, &dep_tags_files, tmp_band_tags.path())?;
+        } else {
+            copy_tags(config, tags, tmp_band_tags.path())?;
+        }
+
+        let validate = false;
+        finish_final_tags(config, tmp_band_tags.path(), &root_dirs, filtered_dirs, validate, &source.depth_tags_file(band))?;
+    }
+
+    Ok(())
+}
+
+/// the band of '--split-by-depth' of the root `source` containing the tags of `other`: '0'
+/// for `source` itself, '1' for its direct dependencies and '2plus' for its indirect ones
+pub fn depth_band(dep_tree: &DepTree, source: &Source, other: &Source) -> Option<&'static str> {
+    if other.id == source.id {
+        Some("0")
+    } else if dep_tree.dependencies(source).any(|d| d.id == other.id) {
+        Some("1")
+    } else if dep_tree.descendants(source).iter().any(|d| d.id == other.id) {
+        Some("2plus")
+    } else {
+        None
+    }
+}
+
+/// A failed tags creation of a source.
+#[derive(Serialize)]
+struct SourceFailure {
+    /// the 'Cargo.toml' name of the source
+    name: String,
+
+    /// the 'Cargo.toml' version of the source
+    version: String,
+
+    /// the error message of the failure
+    error: String,
+
+    #[serde(skip)]
+    id: SourceId
+}
+
+/// The JSON report of all failed sources of '--continue-on-error'.
+#[derive(Serialize)]
+struct FailureReport<'a> {
+    failures: &'a [SourceFailure]
+}
+
+/// The failures of the tags creation of the sources, shared between
+/// the threads of the thread pool.
+struct SourceFailures {
+    failures: Mutex<Vec<SourceFailure>>
+}
+
+impl SourceFailures {
+    fn new() -> SourceFailures {
+        SourceFailures { failures: Mutex::new(Vec::new()) }
+    }
+
+    fn record(&self, source: &Source, result: RtResult<()>) {
+        if let Err(err) = result {
+            let mut failures = self.failures.lock().unwrap();
+            failures.push(SourceFailure {
+                name: source.name.clone(),
+                version: source.version.to_string(),
+                error: err.to_string().trim().to_string(),
+                id: source.id
+            });
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.failures.lock().unwrap().len()
+    }
+
+    fn sources(&self) -> FnvHashSet<SourceId> {
+        self.failures.lock().unwrap().iter().map(|f| f.id).collect()
+    }
+
+    /// Returns the first failure as error, if errors
+    /// shouldn't be collected by '--continue-on-error'.
+    fn check(&self, config: &Config) -> RtResult<()> {
+        if config.continue_on_error {
+            return Ok(());
+        }
+
+        let failures = self.failures.lock().unwrap();
+        match failures.first() {
+            Some(failure) => Err(failure.error.clone().into()),
+            None => Ok(())
+        }
+    }
+
+    /// Returns a JSON report of all failures as error, if any occurred and they're
+    /// more than allowed by '--max-failures' for the 'num_updated' sources,
+    /// otherwise the report is only printed.
+    fn report(&self, config: &Config, num_updated: usize) -> RtResult<()> {
+        let mut failures = self.failures.lock().unwrap();
+        if failures.is_empty() {
+            return Ok(());
+        }
+
+        failures.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        let report = serde_json::to_string_pretty(&FailureReport { failures: &failures })?;
+        match config.max_failures {
+            Some(max) if ! max.is_exceeded(failures.len(), num_updated) => {
+                if ! config.quiet {
+                    eprintln!("{}", report);
+                }
+
+                info!(config, "{} of {} sources failed, which is within the allowed failures", failures.len(), num_updated);
+                Ok(())
+            }
+
+            _ => Err(report.into())
+        }
+    }
+}
+
+/// Returns all sources with the name 'crate_name', all of their
+/// dependent (ancestor) sources and the roots.
+fn impacted_sources<'a>(dep_tree: &'a DepTree, crate_name: &str) -> RtResult<Vec<&'a Source>> {
+    let crate_sources: Vec<_> = dep_tree.all_sources().filter(|s| s.name == crate_name).collect();
+    if crate_sources.is_empty() {
+        return Err(format!("Couldn't find crate '{}' in the dependency tree!", crate_name).into());
+    }
+
+    let mut sources = dep_tree.ancestors(&crate_sources);
+    sources.extend(&crate_sources);
+    sources.extend(dep_tree.roots());
+    unique_sources(&mut sources);
+    Ok(sources)
+}
+
+/// creates the tags of `source` - without its dependencies - and writes them to `tags_file`,
+/// for crates with enabled 'doc_tests' the extracted doc-tests are tagged as well
+fn create_source_tags(config: &Config, source: &Source, tags_file: &Path) -> RtResult<()> {
+    if let Some(crate_config) = config.crate_config(&source.name) {
+        run_pre_tag_command(config, source, &crate_config.pre_tag_command)?;
+    }
+
+    let mut src_dirs = vec![source.dir.clone()];
+    if let Some(crate_config) = config.crate_config(&source.name) {
+        if crate_config.doc_tests {
+            if let Some(doc_tests_dir) = extract_doc_tests(config, source)? {
+                src_dirs.push(doc_tests_dir);
+            }
+        }
+    }
+
+    // the target directories and nested packages, e.g. of git submodules
+    let mut excluded_dirs = Vec::new();
+    for dir in &src_dirs {
+        excluded_dirs.extend(foreign_dirs(dir)?);
+    }
+
+    // the target directories might contain generated code, e.g. of build scripts
+    let mut target_dirs = Vec::new();
+    if config.index_target_dir(&source.name) {
+        let (targets, others) = excluded_dirs.into_iter().partition(|d: &PathBuf| is_cargo_target_dir(d));
+        target_dirs = targets;
+        excluded_dirs = others;
+        if ! target_dirs.is_empty() {
+            verbose!(config, "\nIncluding target directories of '{}':", source.name);
+            for dir in &target_dirs {
+                verbose!(config, "   {}", dir.display());
+            }
+        }
+    }
+
+    if ! excluded_dirs.is_empty() {
+        verbose!(config, "\nExcluding target directories and nested packages of '{}':", source.name);
+        for dir in &excluded_dirs {
+            verbose!(config, "   {}", dir.display());
+        }
+    }
+
+    // the build script output files are listed explicitly, but might be in an excluded target directory
+    let mut ctags_args: Vec<String> = excluded_dirs.iter()
+        .filter(|d| ! source.out_files.iter().any(|f| f.starts_with(d)))
+        .map(|d| format!("--exclude={}", d.display()))
+        .collect();
+
+    // the sources of dependencies don't change, so their line numbers are stable
+    if config.compact_deps && ! source.is_root && config.tags_spec.kind == TagsKind::Vi {
+        ctags_args.push("--excmd=number".to_string());
+    }
+
+    // the files which aren't tagged, but which are in the source directories
+    let mut skipped_files = Vec::new();
+
+    // the test code of the workspace members gets its own tags file
+    if config.split_tests && source.is_root {
+        skipped_files.extend(cfg_test_files(&source.dir, &config.exclude_paths)?);
+    }
+
+    if let Some(ref target) = config.active_cfg_target {
+        let inactive_files = inactive_cfg_files(&source.dir, &config.exclude_paths, target)?;
+        if ! inactive_files.is_empty() {
+            verbose!(config, "\nSkipping the files of '{}' for other targets:", source.name);
+            for file in &inactive_files {
+                verbose!(config, "   {}", file.display());
+            }
+        }
+
+        skipped_files.extend(inactive_files);
+    }
+
+    if let Some(max_size) = config.max_file_size {
+        let mut big_files = Vec::new();
+        for dir in src_dirs.iter().chain(&target_dirs) {
+            big_files.extend(oversized_files(dir, &config.exclude_paths, max_size)?
+                .into_iter()
+                .filter(|(f, _)| ! excluded_dirs.iter().any(|d| f.starts_with(d))));
+        }
+
+        if ! big_files.is_empty() {
+            verbose!(config, "\nSkipping the files of '{}' bigger than {} bytes:", source.name, max_size);
+            for (file, size) in &big_files {
+                verbose!(config, "   {} ({} bytes)", file.display(), size);
+            }
+        }
+
+        skipped_files.extend(big_files.into_iter().map(|(f, _)| f));
+    }
+
+    if ! source.out_files.is_empty() {
+        verbose!(config, "\nIncluding build script output files of '{}':", source.name);
+        for file in &source.out_files {
+            verbose!(config, "   {}", file.display());
+        }
+    }
+
+    if ! source.target_files.is_empty() {
+        verbose!(config, "\nOnly tagging the module files of the targets of '{}':", source.name);
+        for file in &source.target_files {
+            verbose!(config, "   {}", file.display());
+        }
+    }
+
+    if ! skipped_files.is_empty() || ! source.out_files.is_empty() || ! source.target_files.is_empty() {
+        skipped_files.sort();
+        let mut files = Vec::new();
+        for dir in src_dirs.iter().chain(&target_dirs) {
+            let dir_files = if *dir == source.dir && ! source.target_files.is_empty() {
+                module_files(&source.target_files, &config.exclude_paths)?
+            } else {
+                rust_files(dir, &config.exclude_paths)?
+            };
+
+            files.extend(dir_files
+                .into_iter()
+                .filter(|f| skipped_files.binary_search(f).is_err())
+                .filter(|f| ! excluded_dirs.iter().any(|d| f.starts_with(d))));
+        }
+
+        for file in &source.out_files {
+            if ! files.contains(file) {
+                files.push(file.clone());
+            }
+        }
+
+        src_dirs = files;
+    } else if ! config.exclude_paths.is_empty() {
+        // the listed files of the source directories don't contain the ones of target
+        // directories, which ctags only finds by recursing into the source directories
+        src_dirs.extend(target_dirs);
+    }
+
+    create_tags_with_args(config, &src_dirs, &excluded_dirs, &ctags_args, tags_file)?;
+    if config.embed_provenance {
+        add_provenance_fields(config, source, tags_file)?;
+    }
+
+    Ok(())
+}
+
+/// if the source file `file` of `source` isn't tagged by `create_source_tags`, because it's
+/// matching '--exclude-path', test code of '--split-tests', only for other targets than the
+/// one of '--active-cfg-only', bigger than '--max-file-size' or not a module file of the
+/// targets of '--index-target-files-only'
+pub fn is_skipped_file(config: &Config, source: &Source, file: &Path) -> RtResult<bool> {
+    let excluded_dirs = file.ancestors()
+        .skip(1)
+        .take_while(|d| d.starts_with(&source.dir) && *d != source.dir);
+
+    if is_excluded(file, false, &config.exclude_paths) || excluded_dirs.clone().any(|d| is_excluded(d, true, &config.exclude_paths)) {
+        return Ok(true);
+    }
+
+    if config.split_tests && source.is_root && cfg_test_files(&source.dir, &config.exclude_paths)?.iter().any(|f| f == file) {
+        return Ok(true);
+    }
+
+    if let Some(ref target) = config.active_cfg_target {
+        if inactive_cfg_files(&source.dir, &config.exclude_paths, target)?.iter().any(|f| f == file) {
+            return Ok(true);
+        }
+    }
+
+    if let Some(max_size) = config.max_file_size {
+        if file.metadata()?.len() > max_size {
+            return Ok(true);
+        }
+    }
+
+    if ! source.target_files.is_empty() && ! module_files(&source.target_files, &config.exclude_paths)?.iter().any(|f| f == file) {
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// runs the 'pre_tag_command' `command` of the crate config of `source` in its source directory,
+/// the files it creates there get tagged. The command is only given by the config file, so
+/// that the sources of dependencies can't make rusty-tags run anything.
+fn run_pre_tag_command(config: &Config, source: &Source, command: &[String]) -> RtResult<()> {
+    let (program, args) = match command.split_first() {
+        Some(cmd) => cmd,
+        None => return Ok(())
+    };
+
+    let mut cmd = Command::new(program);
+    cmd.args(args).current_dir(&source.dir);
+    verbose!(config, "\nRunning pre tag command of '{}':\n   {:?}\n   in: {}", source.name, cmd, source.dir.display());
+
+    let output = cmd.output()
+        .map_err(|err| format!("Execution of the pre tag command {:?} of '{}' failed: {}", command, source.name, err))?;
+
+    if ! output.status.success() {
+        let mut msg = format!("The pre tag command {:?} of '{}' failed with {}", command, source.name, output.status);
+        if ! output.stderr.is_empty() {
+            msg.push_str(&format!(":\n{}", String::from_utf8_lossy(&output.stderr)));
+        }
+
+        return Err(msg.into());
+    }
+
+    Ok(())
+}
+
+/// adds the `crate` and `version` fields of `source` to every vi tag in `tags_file`,
+/// which only contains the tags of `source`
+pub fn add_provenance_fields(config: &Config, source: &Source, tags_file: &Path) -> RtResult<()> {
+    if config.tags_spec.kind != TagsKind::Vi {
+        return Ok(());
+    }
+
+    let fields = format!("\tcrate:{}\tversion:{}", source.name, source.version);
+    let contents = read_file(tags_file)?;
+    let mut tag_file = BufWriter::with_capacity(64000, File::create(tags_file)?);
+    for line in contents.lines() {
+        tag_file.write_all(line.as_bytes())?;
+        if ! line.starts_with('!') && ! line.is_empty() {
+            // extension fields need the ';"' after the search pattern or line number
+            if ! line.contains(";\"\t") && ! line.ends_with(";\"") {
+                tag_file.write_all(b";\"")?;
+            }
+
+            tag_file.write_all(fields.as_bytes())?;
+        }
+
+        tag_file.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+/// checks for '--require-member-tags' that the tags file of each workspace member contains
+/// at least one tag of the member itself, an empty member most likely has a wrong source path
+fn check_member_tags(config: &Config, dep_tree: &DepTree) -> RtResult<()> {
+    let mut empty_members = Vec::new();
+    for root in dep_tree.roots() {
+        let tags_dir = root.tags_file.parent().unwrap_or(Path::new("."));
+        let has_tags = root.tags_file.is_file() && read_tag_entries(config, &root.tags_file)?
+            .iter()
+            .flat_map(|e| absolute_tag_paths(config.mixed_paths, &config.relative_roots, tags_dir, Path::new(&e.path)))
+            .any(|path| path.starts_with(&root.dir));
+
+        if ! has_tags {
+            empty_members.push(format!("   {} ({})", root.name, root.dir.display()));
+        }
+    }
+
+    if ! empty_members.is_empty() {
+        empty_members.sort();
+        return Err(format!("Found no tags of the workspace members, are their source paths correct?\n{}",
+                           empty_members.join("\n")).into());
+    }
+
+    Ok(())
+}
+
+/// the absolute paths of the file of a tag with the path `path` in a final tags file in `tags_dir`,
+/// which is relative to `tags_dir` for '--mixed-paths' and relative to the most specific one of
+/// `relative_roots` containing the file for '--relative-roots'. Only a path relative to several
+/// roots, which none of them contains more specifically, might be the path of several files.
+fn absolute_tag_paths(mixed_paths: bool, relative_roots: &[PathBuf], tags_dir: &Path, path: &Path) -> Vec<PathBuf> {
+    if path.is_absolute() {
+        return vec![normalize_path(path)];
+    }
+
+    if mixed_paths {
+        return vec![normalize_path(&tags_dir.join(path))];
+    }
+
+    // the path can't be relative to a root, if a more specific root contains the file,
+    // if the path is still ambiguous, then only the existing files remain
+    let candidates: Vec<PathBuf> = relative_roots.iter()
+        .map(|r| normalize_path(&r.join(path)))
+        .filter(|p| relative_to_roots(p, relative_roots).as_deref() == Some(path))
+        .collect();
+
+    if candidates.len() > 1 {
+        candidates.into_iter().filter(|p| p.is_file()).collect()
+    } else {
+        candidates
+    }
+}
+
+/// creates the tags of the test code of each workspace member - the test targets
+/// and the files with test modules - and writes them to '<tags file>.tests'
+fn update_test_tags(config: &Config, dep_tree: &DepTree) -> RtResult<()> {
+    for root in dep_tree.roots() {
+        let mut test_paths = root.test_paths.clone();
+        test_paths.extend(cfg_test_files(&root.dir, &config.exclude_paths)?);
+
+        let tests_tags_file = {
+            let mut file = root.tags_file.clone().into_os_string();
+            file.push(".tests");
+            PathBuf::from(file)
+        };
+
+        let tmp_tags = NamedTempFile::new_in(&config.cache_dir)?;
+        if test_paths.is_empty() {
+            verbose!(config, "\nNo test code found for '{}'", root.name);
+        } else {
+            create_tags(config, &test_paths, tmp_tags.path())?;
+        }
+
+        move_tags(config, tmp_tags.path(), &tests_tags_file)?;
+    }
+
+    Ok(())
+}
+
 /// creates tags recursive for the directory hierarchies starting at `src_dirs`
 /// and writes them to `tags_file`
 pub fn create_tags<P1, P2>(config: &Config, src_dirs: &[P1], tags_file: P2) -> RtResult<()>
     where P1: AsRef<Path>,
           P2: AsRef<Path>
+{
+    create_tags_with_args(config, src_dirs, &[], &[], tags_file)
+}
+
+/// like `create_tags`, but the directories `exclude_dirs` are skipped
+/// and ctags gets the additional arguments `ctags_args`
+pub fn create_tags_with_args<P1, P2>(config: &Config,
+                                 src_dirs: &[P1],
+                                 exclude_dirs: &[PathBuf],
+                                 ctags_args: &[String],
+                                 tags_file: P2)
+                                 -> RtResult<()>
+    where P1: AsRef<Path>,
+          P2: AsRef<Path>
 {
     let mut cmd = config.tags_spec.ctags_command();
-    cmd.arg("-o")
+    cmd.args(ctags_args)
+       .arg("-o")
        .arg(tags_file.as_ref());
 
-    for dir in src_dirs {
-        cmd.arg(dir.as_ref());
+    // with excluded paths ctags gets an explicit list of the not excluded files
+    let _file_list;
+    if config.exclude_paths.is_empty() {
+        for dir in src_dirs {
+            cmd.arg(dir.as_ref());
+        }
+    } else {
+        let file_list = NamedTempFile::new_in(&config.cache_dir)?;
+        write_file_list(config, src_dirs, exclude_dirs, file_list.path())?;
+        cmd.arg("-L").arg(file_list.path());
+        _file_list = file_list;
     }
 
     if config.verbose {
@@ -218,6 +889,108 @@ pub fn create_tags<P1, P2>(config: &Config, src_dirs: &[P1], tags_file: P2) -> R
         return Err(msg.into());
     }
 
+    if config.tags_spec.kind == TagsKind::Vi && config.pseudo_tags != PseudoTags::Keep {
+        filter_pseudo_tags(config, tags_file.as_ref())?;
+    }
+
+    Ok(())
+}
+
+/// writes the rust source files of `src_dirs` - which might also contain files -
+/// without the ones in `exclude_dirs` or matching `config.exclude_paths` into
+/// `file_list`, one per line
+fn write_file_list<P: AsRef<Path>>(config: &Config, src_dirs: &[P], exclude_dirs: &[PathBuf], file_list: &Path) -> RtResult<()> {
+    let mut writer = BufWriter::new(File::create(file_list)?);
+    for src in src_dirs {
+        let src = src.as_ref();
+        if src.is_dir() {
+            for file in rust_files(src, &config.exclude_paths)? {
+                if ! exclude_dirs.iter().any(|d| file.starts_with(d)) {
+                    writeln!(writer, "{}", file.display())?;
+                }
+            }
+        } else if ! is_excluded(src, false, &config.exclude_paths) {
+            writeln!(writer, "{}", src.display())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// the pseudo tags of the format and the sorting of vi tags
+const TAG_FILE_FORMAT: &str = "!_TAG_FILE_FORMAT\t2\t/extended format; --format=1 will not append ;\" to lines/";
+const TAG_FILE_SORTED: &str = "!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/";
+
+/// the pseudo tag of the program name for '--pseudo-tags=minimal', which
+/// replaces the one of ctags, whose version is left out
+const TAG_PROGRAM_NAME: &str = "!_TAG_PROGRAM_NAME\trusty-tags\t//";
+
+/// removes the pseudo tags - the lines starting with '!_TAG_' - created
+/// by ctags from `tags_file` depending on `config.pseudo_tags`
+fn filter_pseudo_tags(config: &Config, tags_file: &Path) -> RtResult<()> {
+    let contents = read_file(tags_file)?;
+    let mut tag_file = BufWriter::with_capacity(64000, File::create(tags_file)?);
+    for line in contents.lines() {
+        if let Some(line) = filtered_pseudo_tag(config.pseudo_tags, line) {
+            tag_file.write_all(line.as_bytes())?;
+            tag_file.write_all(b"\n")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// the line to write for `line` of the tags created by ctags, which is only
+/// changed or removed for pseudo tags depending on `pseudo_tags`
+fn filtered_pseudo_tag(pseudo_tags: PseudoTags, line: &str) -> Option<&str> {
+    if ! line.starts_with('!') {
+        return Some(line);
+    }
+
+    match pseudo_tags {
+        PseudoTags::Keep    => Some(line),
+        PseudoTags::Strip   => None,
+        PseudoTags::Minimal => {
+            if line.starts_with("!_TAG_PROGRAM_NAME") {
+                Some(TAG_PROGRAM_NAME)
+            } else if line.starts_with("!_TAG_FILE_FORMAT") || line.starts_with("!_TAG_FILE_SORTED") {
+                Some(line)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// the pseudo tags written at the start of merged vi tags depending on `pseudo_tags`,
+/// the ones of the merged tags files are dropped
+fn merged_pseudo_tags(pseudo_tags: PseudoTags) -> &'static [&'static str] {
+    match pseudo_tags {
+        PseudoTags::Keep    => &[TAG_FILE_FORMAT, TAG_FILE_SORTED],
+        PseudoTags::Strip   => &[],
+        PseudoTags::Minimal => &[TAG_FILE_FORMAT, TAG_FILE_SORTED, TAG_PROGRAM_NAME]
+    }
+}
+
+/// ensures that the directory of `tags_file` exists, which is
+/// created if `config.create_output_dir` is set
+pub fn ensure_output_dir(config: &Config, tags_file: &Path) -> RtResult<()> {
+    let dir = match tags_file.parent() {
+        Some(dir) if ! dir.as_os_str().is_empty() => dir,
+        _ => return Ok(())
+    };
+
+    if dir.is_dir() {
+        return Ok(());
+    }
+
+    if ! config.create_output_dir {
+        return Err(format!("Missing directory '{}' for the tags file '{}'! Create it or use '--create-output-dir'.",
+                           dir.display(), tags_file.display()).into());
+    }
+
+    verbose!(config, "\nCreating directory for tags file:\n   {}", dir.display());
+    create_dir_all(dir)?;
     Ok(())
 }
 
@@ -237,6 +1010,258 @@ pub fn move_tags(config: &Config, from_tags: &Path, to_tags: &Path) -> RtResult<
     Ok(())
 }
 
+/// records the version of the ctags executable beside of the cached tags
+/// of `source`, for the detection of a ctags change by '--reindex-on-ctags-change'
+fn write_ctags_version(config: &Config, source: &Source) -> RtResult<()> {
+    let mut file = File::create(source.ctags_version_file())?;
+    writeln!(file, "{}", config.ctags_version)?;
+    Ok(())
+}
+
+/// records the hash of the contents of the source files of the path dependency `source` beside
+/// of its cached tags, for the detection of changed source files by 'Source::needs_tags_update'
+fn write_contents_hash(source: &Source) -> RtResult<()> {
+    let mut file = File::create(source.contents_hash_file())?;
+    writeln!(file, "{}", contents_hash(&source.dir)?)?;
+    Ok(())
+}
+
+/// makes the paths of the vi tags in `tags_file`, which are in one of the directories
+/// `root_dirs` of the workspace members, relative to `tags_dir`, the directory of the
+/// final tags file, for '--mixed-paths'
+fn make_paths_relative(tags_file: &Path, root_dirs: &[&Path], tags_dir: &Path) -> RtResult<()> {
+    let contents = read_file(tags_file)?;
+    let mut tag_file = BufWriter::with_capacity(64000, File::create(tags_file)?);
+    for line in contents.lines() {
+        let mut fields = line.splitn(3, '\t');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(name), Some(path), Some(rest)) if ! line.starts_with('!') && root_dirs.iter().any(|d| Path::new(path).starts_with(d)) => {
+                let path = relative_path(tags_dir, Path::new(path));
+                writeln!(tag_file, "{}\t{}\t{}", name, path.display(), rest)?;
+            }
+
+            _ => {
+                tag_file.write_all(line.as_bytes())?;
+                tag_file.write_all(b"\n")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// makes the paths of the vi tags in `tags_file` relative to the most specific one of the
+/// directories `roots` containing them, for '--relative-roots', the other paths stay absolute
+fn make_paths_relative_to_roots(tags_file: &Path, roots: &[PathBuf]) -> RtResult<()> {
+    let contents = read_file(tags_file)?;
+    let mut tag_file = BufWriter::with_capacity(64000, File::create(tags_file)?);
+    for line in contents.lines() {
+        let mut fields = line.splitn(3, '\t');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(name), Some(path), Some(rest)) if ! line.starts_with('!') => {
+                match relative_to_roots(Path::new(path), roots) {
+                    Some(path) => writeln!(tag_file, "{}\t{}\t{}", name, path.display(), rest)?,
+                    None       => writeln!(tag_file, "{}", line)?
+                }
+            }
+
+            _ => {
+                tag_file.write_all(line.as_bytes())?;
+                tag_file.write_all(b"\n")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// removes the vi tags in `tags_file` of the crate of '--module-filter', whose files are
+/// in one of its source directories `crate_dirs`, which aren't in the module `module_path`
+/// or one of its submodules. The module of a tag is given by the path of its file and
+/// by its 'module' scope field, a tag of a module itself is also kept.
+fn filter_module_tags(tags_file: &Path, crate_dirs: &[&Path], module_path: &[String]) -> RtResult<()> {
+    let contents = read_file(tags_file)?;
+    let mut tag_file = BufWriter::with_capacity(64000, File::create(tags_file)?);
+    for line in contents.lines() {
+        let mut fields = line.splitn(3, '\t');
+        let keep = match (fields.next(), fields.next(), fields.next()) {
+            (Some(name), Some(path), Some(rest)) if ! line.starts_with('!') => {
+                match crate_dirs.iter().find_map(|d| Path::new(path).strip_prefix(d).ok()) {
+                    Some(rel_path) => {
+                        let mut tag_path = file_module_path(rel_path);
+                        tag_path.extend(scope_module_path(rest));
+                        tag_path.push(name.to_string());
+                        tag_path.starts_with(module_path)
+                    }
+
+                    None => true
+                }
+            }
+
+            _ => true
+        };
+
+        if keep {
+            tag_file.write_all(line.as_bytes())?;
+            tag_file.write_all(b"\n")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// the module path of the rust source file `rel_path`, which is relative to the source
+/// directory of its crate, e.g. '["net", "tcp"]' for 'net/tcp.rs' or 'net/tcp/mod.rs'
+fn file_module_path(rel_path: &Path) -> Vec<String> {
+    let mut module_path: Vec<String> = rel_path.with_extension("")
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+
+    if module_path.last().is_some_and(|m| m == "mod" || m == "lib" || m == "main") {
+        module_path.pop();
+    }
+
+    module_path
+}
+
+/// the module path of the 'module' scope field in the extension fields `fields` of a vi tag
+fn scope_module_path(fields: &str) -> Vec<String> {
+    let fields = match fields.rfind(";\"\t") {
+        Some(idx) => &fields[idx + 3..],
+        None => return Vec::new()
+    };
+
+    fields.split('\t')
+        .find_map(|f| f.strip_prefix("module:"))
+        .map(|m| m.split("::").map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// strips the vi tags in `tags_file` to their name and path for '--format=minimal',
+/// the tags which only differed in other fields are merged
+fn strip_to_minimal(tags_file: &Path) -> RtResult<()> {
+    let contents = read_file(tags_file)?;
+    let mut pseudo_tag_lines = Vec::new();
+    let mut lines = Vec::new();
+    for line in contents.lines() {
+        if line.starts_with('!') {
+            pseudo_tag_lines.push(line);
+        } else if ! line.is_empty() {
+            let mut fields = line.splitn(3, '\t');
+            match (fields.next(), fields.next()) {
+                (Some(name), Some(path)) => lines.push(&line[..name.len() + 1 + path.len()]),
+                _ => lines.push(line)
+            }
+        }
+    }
+
+    lines.sort_unstable();
+    lines.dedup();
+
+    let mut tag_file = BufWriter::with_capacity(64000, File::create(tags_file)?);
+    for line in pseudo_tag_lines.iter().chain(lines.iter()) {
+        tag_file.write_all(line.as_bytes())?;
+        tag_file.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+/// converts the line endings of the vi tags file `tags_file` to `config.line_ending`,
+/// the cached tags are kept with the line endings of ctags
+fn convert_line_endings(config: &Config, tags_file: &Path) -> RtResult<()> {
+    let new_line = config.line_ending.as_str();
+    if config.tags_spec.kind != TagsKind::Vi || new_line == "\n" {
+        return Ok(());
+    }
+
+    let contents = read_file(tags_file)?;
+    let mut tag_file = BufWriter::with_capacity(64000, File::create(tags_file)?);
+    for line in contents.lines() {
+        tag_file.write_all(line.as_bytes())?;
+        tag_file.write_all(new_line.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// replaces the tags of the source file `src_file` in `into_tag_file` by
+/// the tags in `file_tag_file`, which only contains tags of `src_file`
+pub fn splice_tags(config: &Config, file_tag_file: &Path, src_file: &Path, into_tag_file: &Path) -> RtResult<()> {
+    verbose!(config, "\nSplicing tags of '{}' ...\n   from:\n      {}\n   into:\n      {}",
+             src_file.display(), file_tag_file.display(), into_tag_file.display());
+
+    let src_file = src_file.display().to_string();
+    let file_contents = read_file(file_tag_file)?;
+    let into_contents = read_tags_file(into_tag_file)?;
+    let into_compression = detect_compression(into_tag_file)?;
+    let into_dir = into_tag_file.parent().unwrap_or(Path::new("."));
+    let tmp_tags = NamedTempFile::new_in(into_dir)?;
+    {
+        let mut tag_file = BufWriter::with_capacity(64000, File::create(tmp_tags.path())?);
+        match config.tags_spec.kind {
+            TagsKind::Vi => {
+                let mut pseudo_tag_lines = Vec::new();
+                let mut lines = Vec::new();
+                for line in into_contents.lines() {
+                    if line.starts_with('!') {
+                        pseudo_tag_lines.push(line);
+                    } else if line.split('\t').nth(1) != Some(src_file.as_str()) {
+                        lines.push(line);
+                    }
+                }
+
+                lines.extend(file_contents.lines().filter(|line| ! line.is_empty() && ! line.starts_with('!')));
+                lines.sort_unstable();
+                lines.dedup();
+
+                // keep the line endings of '--line-ending'
+                let new_line = if into_contents.contains("\r\n") { "\r\n" } else { "\n" };
+                for line in pseudo_tag_lines.iter().chain(lines.iter()) {
+                    tag_file.write_all(line.as_bytes())?;
+                    tag_file.write_all(new_line.as_bytes())?;
+                }
+            }
+
+            TagsKind::Emacs => {
+                // the sections of every source file start with a form feed line, the
+                // includes - added by 'merge_tags' - follow the last section
+                let mut includes = Vec::new();
+                for section in into_contents.split("\x0c\n").filter(|s| ! s.is_empty()) {
+                    let mut section_lines = Vec::new();
+                    for line in section.lines() {
+                        if line.ends_with(",include") && ! line.contains('\x7f') {
+                            includes.push(line);
+                        } else {
+                            section_lines.push(line);
+                        }
+                    }
+
+                    let section_file = section_lines.first().and_then(|header| header.rsplitn(2, ',').last());
+                    if section_lines.is_empty() || section_file == Some(src_file.as_str()) {
+                        continue;
+                    }
+
+                    tag_file.write_all(b"\x0c\n")?;
+                    for line in section_lines {
+                        tag_file.write_all(line.as_bytes())?;
+                        tag_file.write_all(b"\n")?;
+                    }
+                }
+
+                tag_file.write_all(file_contents.as_bytes())?;
+                for line in includes {
+                    tag_file.write_all(line.as_bytes())?;
+                    tag_file.write_all(b"\n")?;
+                }
+            }
+        }
+    }
+
+    move_compressed(config, into_compression, tmp_tags.path(), into_tag_file)
+}
+
 /// merges the library tag file `lib_tag_file` and its dependency tag files
 /// `dependency_tag_files` into `into_tag_file`
 fn merge_tags(config: &Config,
@@ -265,18 +1290,8 @@ fn merge_tags(config: &Config,
 
             let mut file_contents: Vec<String> = Vec::with_capacity(dependency_tag_files.len() + 1);
             let mut num_lines: usize = 0;
-            {
-                let mut file = File::open(lib_tag_file)?;
-                let mut contents = String::new();
-                file.read_to_string(&mut contents)?;
-                num_lines += contents.lines().count();
-                file_contents.push(contents);
-            }
-
-            for file in dependency_tag_files {
-                let mut file = File::open(file)?;
-                let mut contents = String::new();
-                file.read_to_string(&mut contents)?;
+            for file in Some(&lib_tag_file).into_iter().chain(dependency_tag_files) {
+                let contents = read_tags_file(file)?;
                 num_lines += contents.lines().count();
                 file_contents.push(contents);
             }
@@ -304,10 +1319,12 @@ fn merge_tags(config: &Config,
                 .write(true)
                 .open(into_tag_file)?);
 
-            tag_file.write_fmt(format_args!("{}\n", "!_TAG_FILE_FORMAT	2	/extended format; --format=1 will not append ;\" to lines/"))?;
-            tag_file.write_fmt(format_args!("{}\n", "!_TAG_FILE_SORTED	1	/0=unsorted, 1=sorted, 2=foldcase/"))?;
-
             let new_line = "\n".as_bytes();
+            for line in merged_pseudo_tags(config.pseudo_tags) {
+                tag_file.write_all(line.as_bytes())?;
+                tag_file.write_all(new_line)?;
+            }
+
             for line in merged_lines {
                 tag_file.write_all(line.as_bytes())?;
                 tag_file.write_all(new_line)?;
@@ -341,7 +1358,7 @@ type CrateName = String;
 
 /// searches in the file `<src_dir>/lib.rs` for external crates
 /// that are reexpored and returns their names
-fn find_reexported_crates(src_dir: &Path) -> RtResult<Vec<CrateName>> {
+pub fn find_reexported_crates(src_dir: &Path) -> RtResult<Vec<CrateName>> {
     let lib_file = src_dir.join("lib.rs");
     if ! lib_file.is_file() {
         return Ok(Vec::new());
@@ -399,3 +1416,238 @@ fn find_reexported_crates(src_dir: &Path) -> RtResult<Vec<CrateName>> {
 
     Ok(reexp_crates)
 }
+
+/// A single tag read from a vi or emacs tags file.
+pub struct TagEntry {
+    /// the name of the tagged item
+    pub name: String,
+
+    /// the path to the source file of the tagged item
+    pub path: String,
+
+    /// the source text of the tagged item, the search pattern
+    /// for vi tags and the initial text of the line for emacs tags
+    pub pattern: String,
+
+    /// the extension fields of vi tags, e.g. 'kind' or 'implementation'
+    pub fields: Vec<(String, String)>
+}
+
+impl TagEntry {
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.fields.iter().find(|f| f.0 == key).map(|f| f.1.as_str())
+    }
+}
+
+/// reads all tags of `tags_file`, for emacs tags the included
+/// tags files are also read
+pub fn read_tag_entries(config: &Config, tags_file: &Path) -> RtResult<Vec<TagEntry>> {
+    let mut entries = Vec::new();
+    match config.tags_spec.kind {
+        TagsKind::Vi => {
+            let contents = read_tags_file(tags_file)?;
+            for line in contents.lines() {
+                if line.starts_with('!') {
+                    continue;
+                }
+
+                if let Some(entry) = parse_vi_tag(line) {
+                    entries.push(entry);
+                }
+            }
+        }
+
+        TagsKind::Emacs => {
+            let mut visited = FnvHashSet::default();
+            read_emacs_tag_entries(tags_file, &mut visited, &mut entries)?;
+        }
+    }
+
+    Ok(entries)
+}
+
+fn parse_vi_tag(line: &str) -> Option<TagEntry> {
+    let mut split = line.splitn(3, '\t');
+    let name = split.next()?;
+    let path = split.next()?;
+    let rest = split.next()?;
+
+    // the excommand might contain tabs, so only the part
+    // after the last ';"' contains the extension fields
+    let (excmd, fields) = match rest.rfind(";\"") {
+        Some(idx) => (&rest[..idx], &rest[idx + 2..]),
+        None      => (rest, "")
+    };
+
+    let pattern = if excmd.len() >= 2 && (excmd.starts_with('/') || excmd.starts_with('?')) {
+        let pattern = &excmd[1..excmd.len() - 1];
+        let pattern = pattern.trim_start_matches('^');
+        let pattern = match pattern.strip_suffix('$') {
+            Some(stripped) if ! stripped.ends_with('\\') => stripped,
+            _ => pattern
+        };
+
+        pattern.replace("\\/", "/").replace("\\\\", "\\")
+    } else {
+        String::new()
+    };
+
+    let mut entry_fields = Vec::new();
+    for field in fields.split('\t').filter(|f| ! f.is_empty()) {
+        match field.find(':') {
+            Some(idx) => entry_fields.push((field[..idx].to_string(), field[idx + 1..].to_string())),
+            None      => entry_fields.push(("kind".to_string(), field.to_string()))
+        }
+    }
+
+    Some(TagEntry {
+        name: name.to_string(),
+        path: path.to_string(),
+        pattern,
+        fields: entry_fields
+    })
+}
+
+fn read_emacs_tag_entries(tags_file: &Path,
+                          visited: &mut FnvHashSet<String>,
+                          entries: &mut Vec<TagEntry>)
+                          -> RtResult<()> {
+    if ! visited.insert(tags_file.display().to_string()) {
+        return Ok(());
+    }
+
+    let contents = read_file(tags_file)?;
+    let mut lines = contents.lines();
+    let mut path = String::new();
+    while let Some(line) = lines.next() {
+        // the includes written by 'merge_tags' aren't preceded by a form feed
+        if let Some(included) = line.strip_suffix(",include") {
+            if ! line.contains('\x7f') {
+                read_emacs_tag_entries(Path::new(included), visited, entries)?;
+                continue;
+            }
+        }
+
+        if line == "\x0c" {
+            if let Some(header) = lines.next() {
+                if let Some(included) = header.strip_suffix(",include") {
+                    read_emacs_tag_entries(Path::new(included), visited, entries)?;
+                    path.clear();
+                } else {
+                    path = header.rsplitn(2, ',').last().unwrap_or(header).to_string();
+                }
+            }
+
+            continue;
+        }
+
+        if let Some(idx) = line.find('\x7f') {
+            let pattern = &line[..idx];
+            let name = match line[idx + 1..].find('\x01') {
+                Some(end) => line[idx + 1..idx + 1 + end].to_string(),
+                None      => String::new()
+            };
+
+            entries.push(TagEntry {
+                name,
+                path: path.clone(),
+                pattern: pattern.to_string(),
+                fields: Vec::new()
+            });
+        }
+    }
+
+    Ok(())
+}
+
+pub fn read_file(file: &Path) -> RtResult<String> {
+    let mut file = File::open(file)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn absolute_tag_paths_of_absolute_paths() {
+        let tags_dir = Path::new("/ws/member");
+        assert_eq!(absolute_tag_paths(false, &[], tags_dir, Path::new("/ws/member/src/lib.rs")),
+                   vec![PathBuf::from("/ws/member/src/lib.rs")]);
+        assert_eq!(absolute_tag_paths(true, &[], tags_dir, Path::new("/deps/serde/src/../src/lib.rs")),
+                   vec![PathBuf::from("/deps/serde/src/lib.rs")]);
+    }
+
+    #[test]
+    fn absolute_tag_paths_of_mixed_paths() {
+        let tags_dir = Path::new("/ws/member");
+        assert_eq!(absolute_tag_paths(true, &[], tags_dir, Path::new("src/lib.rs")),
+                   vec![PathBuf::from("/ws/member/src/lib.rs")]);
+
+        // a tag of another member, which starts with 'member' before its '..' is applied
+        assert_eq!(absolute_tag_paths(true, &[], tags_dir, Path::new("../other/src/lib.rs")),
+                   vec![PathBuf::from("/ws/other/src/lib.rs")]);
+    }
+
+    #[test]
+    fn absolute_tag_paths_of_relative_roots() {
+        let tags_dir = Path::new("/repo/crates/b");
+        assert_eq!(absolute_tag_paths(false, &[PathBuf::from("/repo")], tags_dir, Path::new("crates/b/src/lib.rs")),
+                   vec![PathBuf::from("/repo/crates/b/src/lib.rs")]);
+
+        // a file in '/repo/crates/a' would have been relative to it, not to '/repo'
+        let roots = [PathBuf::from("/repo"), PathBuf::from("/repo/crates/a")];
+        assert_eq!(absolute_tag_paths(false, &roots, tags_dir, Path::new("crates/a/src/lib.rs")),
+                   vec![PathBuf::from("/repo/crates/a/crates/a/src/lib.rs")]);
+
+        // relative to both roots, but neither file exists
+        assert!(absolute_tag_paths(false, &roots, tags_dir, Path::new("src/lib.rs")).is_empty());
+    }
+
+    #[test]
+    fn absolute_tag_paths_of_relative_roots_with_existing_files() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let repo = repo_dir.path().to_path_buf();
+        let member = repo.join("member");
+        create_dir_all(member.join("src")).unwrap();
+        fs::write(member.join("src/lib.rs"), "").unwrap();
+
+        let roots = [repo.clone(), member.clone()];
+        assert_eq!(absolute_tag_paths(false, &roots, &member, Path::new("src/lib.rs")), vec![member.join("src/lib.rs")]);
+
+        fs::create_dir(repo.join("src")).unwrap();
+        fs::write(repo.join("src/lib.rs"), "").unwrap();
+        assert_eq!(absolute_tag_paths(false, &roots, &member, Path::new("src/lib.rs")),
+                   vec![repo.join("src/lib.rs"), member.join("src/lib.rs")]);
+    }
+
+    #[test]
+    fn filtered_pseudo_tag_of_modes() {
+        let format = "!_TAG_FILE_FORMAT\t2\t/extended format/";
+        let version = "!_TAG_PROGRAM_VERSION\t5.9.0\t/p6.0/";
+        let name = "!_TAG_PROGRAM_NAME\tUniversal Ctags\t/Derived from Exuberant Ctags/";
+        let tag = "main\tsrc/main.rs\t/^fn main() {$/;\"\tf";
+
+        for mode in &[PseudoTags::Keep, PseudoTags::Strip, PseudoTags::Minimal] {
+            assert_eq!(filtered_pseudo_tag(*mode, tag), Some(tag));
+        }
+
+        assert_eq!(filtered_pseudo_tag(PseudoTags::Keep, version), Some(version));
+        assert_eq!(filtered_pseudo_tag(PseudoTags::Strip, format), None);
+        assert_eq!(filtered_pseudo_tag(PseudoTags::Minimal, format), Some(format));
+        assert_eq!(filtered_pseudo_tag(PseudoTags::Minimal, version), None);
+        assert_eq!(filtered_pseudo_tag(PseudoTags::Minimal, name), Some(TAG_PROGRAM_NAME));
+    }
+
+    #[test]
+    fn merged_pseudo_tags_of_modes() {
+        assert_eq!(merged_pseudo_tags(PseudoTags::Keep), &[TAG_FILE_FORMAT, TAG_FILE_SORTED]);
+        assert!(merged_pseudo_tags(PseudoTags::Strip).is_empty());
+        assert_eq!(merged_pseudo_tags(PseudoTags::Minimal), &[TAG_FILE_FORMAT, TAG_FILE_SORTED, TAG_PROGRAM_NAME]);
+        assert!(merged_pseudo_tags(PseudoTags::Minimal).iter().all(|line| ! line.contains("VERSION")));
+    }
+}
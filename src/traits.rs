@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use serde_json;
+
+use rt_result::RtResult;
+use types::DepTree;
+use config::Config;
+use tags::{TagEntry, read_tag_entries};
+use impls::closing_angle_bracket;
+
+/// A trait with supertraits found in the tags.
+#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+struct TraitLocation {
+    /// the supertraits as written in the source, e.g. 'fmt::Debug'
+    supertraits: Vec<String>,
+
+    /// the source file of the trait
+    path: String,
+
+    /// the source line of the trait
+    pattern: String
+}
+
+/// Writes a JSON index of the tags of the roots of 'dep_tree' into 'index_file',
+/// which maps each trait name to its definitions with their supertraits.
+///
+/// This is best-effort: the supertraits are taken from the 'inherits' field, if ctags
+/// filled it, otherwise they're parsed from the 'trait Name: Supertrait' pattern of the
+/// trait tags, so supertraits which aren't on the line of the trait name are missing.
+pub fn emit_trait_hierarchy(config: &Config, dep_tree: &DepTree, index_file: &Path) -> RtResult<()> {
+    info!(config, "Writing trait hierarchy to '{}' ...", index_file.display());
+
+    let mut traits: BTreeMap<String, Vec<TraitLocation>> = BTreeMap::new();
+    for root in dep_tree.roots() {
+        if ! root.tags_file.is_file() {
+            continue;
+        }
+
+        for entry in read_tag_entries(config, &root.tags_file)? {
+            if let Some(location) = trait_location(&entry) {
+                traits.entry(entry.name.clone()).or_default().push(location);
+            }
+        }
+    }
+
+    for locations in traits.values_mut() {
+        locations.sort();
+        locations.dedup();
+    }
+
+    verbose!(config, "Found {} traits with supertraits", traits.len());
+
+    let writer = BufWriter::new(File::create(index_file)?);
+    serde_json::to_writer_pretty(writer, &traits)?;
+    Ok(())
+}
+
+fn trait_location(entry: &TagEntry) -> Option<TraitLocation> {
+    let supertraits = match entry.field("inherits") {
+        Some(inherits) => inherits.split(',').map(|s| s.trim().to_string()).filter(|s| ! s.is_empty()).collect(),
+        None => parse_supertraits(&entry.pattern, &entry.name)?
+    };
+
+    if supertraits.is_empty() {
+        return None;
+    }
+
+    Some(TraitLocation {
+        supertraits,
+        path: entry.path.clone(),
+        pattern: entry.pattern.trim().to_string()
+    })
+}
+
+/// Parses the supertraits of 'trait Name<...>: Supertrait + ...' for the trait 'name',
+/// without lifetimes and '?Sized'.
+fn parse_supertraits(line: &str, name: &str) -> Option<Vec<String>> {
+    let trait_prefix = format!("trait {}", name);
+    let mut rest = &line[line.find(&trait_prefix)? + trait_prefix.len()..];
+    if rest.starts_with('<') {
+        rest = &rest[closing_angle_bracket(rest)? + 1..];
+    }
+
+    rest = rest.trim_start();
+    if ! rest.starts_with(':') || rest.starts_with("::") {
+        return None;
+    }
+
+    rest = &rest[1..];
+    let end = rest.find(" where").or_else(|| rest.find('{')).or_else(|| rest.find(';')).unwrap_or(rest.len());
+    rest = &rest[..end];
+
+    // the supertraits of a trait defined in a macro might be metavariables
+    if rest.contains('$') {
+        return None;
+    }
+
+    // split at the '+' outside of any generic arguments or parentheses
+    let mut supertraits = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, chr) in rest.char_indices() {
+        match chr {
+            '>' if rest[..idx].ends_with('-') => {}
+            '<' | '(' => depth += 1,
+            '>' | ')' => depth -= 1,
+            '+' if depth == 0 => {
+                supertraits.push(rest[start..idx].trim());
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+
+    supertraits.push(rest[start..].trim());
+    Some(supertraits.into_iter()
+        .filter(|s| ! s.is_empty() && ! s.starts_with('\'') && ! s.starts_with('?'))
+        .map(String::from)
+        .collect())
+}
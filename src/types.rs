@@ -1,8 +1,7 @@
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
-use std::collections::hash_map::DefaultHasher;
 use std::collections::HashSet;
-use std::hash::{Hash, Hasher};
+use std::hash::Hasher;
 use std::process::Command;
 use std::ops::{Drop, Deref};
 use std::fmt;
@@ -10,9 +9,11 @@ use std::cmp;
 use std::mem;
 
 use semver::Version;
+use fnv::FnvHasher;
 use rt_result::RtResult;
-use dirs::{rusty_tags_cache_dir, rusty_tags_locks_dir};
+use dirs::rusty_tags_locks_dir;
 use config::Config;
+use files::rust_files;
 
 /// The tree describing the dependencies of the whole cargo project.
 #[derive(Debug)]
@@ -35,6 +36,12 @@ pub struct DepTree {
     parents: Vec<Option<Vec<SourceId>>>
 }
 
+impl Default for DepTree {
+    fn default() -> DepTree {
+        DepTree::new()
+    }
+}
+
 impl DepTree {
     pub fn new() -> DepTree {
         DepTree {
@@ -82,6 +89,18 @@ impl DepTree {
         ancestor_srcs
     }
 
+    /// Get all direct and indirect dependencies of 'source'.
+    pub fn descendants<'a>(&'a self, source: &Source) -> Vec<&'a Source> {
+        let mut descendant_ids = HashSet::new();
+        self.descendants_internal(source.id, &mut descendant_ids);
+
+        let mut descendant_ids: Vec<_> = descendant_ids.into_iter().collect();
+        descendant_ids.sort_unstable();
+        descendant_ids.iter()
+            .filter_map(|id| self.sources[**id].as_ref())
+            .collect()
+    }
+
     /// Reserve space for a new source and return its source id.
     pub fn new_source(&mut self) -> SourceId {
         let id = self.sources.len();
@@ -116,6 +135,27 @@ impl DepTree {
         self.dependencies[*src_id] = Some(dependencies);
     }
 
+    /// Removes all sources which aren't reachable from the roots.
+    pub fn retain_reachable_from_roots(&mut self) {
+        let mut reachable_ids = HashSet::with_capacity(self.sources.len());
+        for id in &self.roots {
+            reachable_ids.insert(*id);
+            self.descendants_internal(*id, &mut reachable_ids);
+        }
+
+        for id in 0..self.sources.len() {
+            if reachable_ids.contains(&SourceId { id }) {
+                if let Some(ref mut parents) = self.parents[id] {
+                    parents.retain(|p| reachable_ids.contains(p));
+                }
+            } else {
+                self.sources[id] = None;
+                self.dependencies[id] = None;
+                self.parents[id] = None;
+            }
+        }
+    }
+
     pub fn compute_depths(&mut self) {
         let roots = mem::replace(&mut self.roots, vec![]);
         for id in &roots {
@@ -150,6 +190,19 @@ impl DepTree {
         self.dependencies[*source.id].as_ref().map(Vec::as_slice)
     }
 
+    fn descendants_internal(&self, source_id: SourceId, descendants: &mut HashSet<SourceId>) {
+        if let Some(ref dep_ids) = self.dependencies[*source_id] {
+            for id in dep_ids {
+                if descendants.contains(id) {
+                    continue;
+                }
+
+                descendants.insert(*id);
+                self.descendants_internal(*id, descendants);
+            }
+        }
+    }
+
     fn ancestors_internal<'a>(&'a self, source_id: SourceId,
                               ancestors: &mut HashSet<SourceId>) {
         if let Some(ref parent_ids) = self.parents[*source_id] {
@@ -270,7 +323,8 @@ pub struct Source {
     /// the root source directory
     pub dir: PathBuf,
 
-    /// hash of 'dir'
+    /// hash of the identity of the source, which
+    /// is independent of the path of 'dir'
     pub hash: String,
 
     /// if the source is a root of the dependency tree,
@@ -293,15 +347,36 @@ pub struct Source {
     /// the tags of the dependencies that have a public
     /// export from the source
     pub cached_tags_file: PathBuf,
+
+    /// the source files - or their directories - of the test
+    /// targets, only set for roots with '--split-tests'
+    pub test_paths: Vec<PathBuf>,
+
+    /// the generated files in the build script output directory
+    /// matching the 'out_dir_globs' of the crate config
+    pub out_files: Vec<PathBuf>,
+
+    /// the crate root files of the targets, whose module files are
+    /// tagged instead of the whole source directory, only set for
+    /// roots with '--index-target-files-only'
+    pub target_files: Vec<PathBuf>,
+
+    /// if the source is a path dependency, whose version doesn't
+    /// identify its sources, so the contents of its source files
+    /// are checked for changes of its cached tags
+    pub is_path_dependency: bool
 }
 
 impl Source {
-    pub fn new(id: SourceId, source_version: &SourceVersion, dir: &Path, is_root: bool, config: &Config) -> RtResult<Source> {
+    /// 'cargo_source' is the 'source' of the package in the cargo metadata, e.g.
+    /// 'registry+https://github.com/rust-lang/crates.io-index', which is 'None' for path dependencies.
+    pub fn new(id: SourceId, source_version: &SourceVersion, dir: &Path, is_root: bool,
+               cargo_source: Option<&str>, config: &Config) -> RtResult<Source> {
         let tags_dir = find_dir_upwards_containing("Cargo.toml", dir).unwrap_or(dir.to_path_buf());
         let tags_file = tags_dir.join(config.tags_spec.file_name());
-        let hash = source_hash(dir);
+        let hash = source_hash(source_version, dir, cargo_source, &tags_options(config));
         let cached_tags_file = {
-            let cache_dir = rusty_tags_cache_dir()?;
+            let cache_dir = &config.cache_dir;
             let file_name = format!("{}-{}.{}", source_version.name, hash, config.tags_spec.file_extension());
             cache_dir.join(&file_name)
         };
@@ -315,7 +390,11 @@ impl Source {
             hash: hash,
             is_root: is_root,
             tags_file: tags_file,
-            cached_tags_file: cached_tags_file
+            cached_tags_file: cached_tags_file,
+            test_paths: Vec::new(),
+            out_files: Vec::new(),
+            target_files: Vec::new(),
+            is_path_dependency: ! is_root && cargo_source.is_none()
         })
     }
 
@@ -332,7 +411,62 @@ impl Source {
             return true;
         }
 
-        ! self.cached_tags_file.is_file() || ! self.tags_file.is_file()
+        ! self.cached_tags_file.is_file() || ! self.tags_file.is_file() || self.ctags_version_changed(config)
+            || self.contents_changed()
+    }
+
+    /// The file beside of the cached tags file recording the version
+    /// of the ctags executable, which created the cached tags.
+    pub fn ctags_version_file(&self) -> PathBuf {
+        let mut file_name = self.cached_tags_file.as_os_str().to_owned();
+        file_name.push(".ctags-version");
+        PathBuf::from(file_name)
+    }
+
+    /// The file beside of the cached tags file recording the hash of the contents
+    /// of the source files of a path dependency, of which the cached tags were created.
+    pub fn contents_hash_file(&self) -> PathBuf {
+        let mut file_name = self.cached_tags_file.as_os_str().to_owned();
+        file_name.push(".contents-hash");
+        PathBuf::from(file_name)
+    }
+
+    /// The tags file beside of 'tags_file' for a depth band of '--split-by-depth',
+    /// e.g. 'rusty-tags.vi.1' for the band '1' of the direct dependencies.
+    pub fn depth_tags_file(&self, band: &str) -> PathBuf {
+        let mut file_name = self.tags_file.as_os_str().to_owned();
+        file_name.push(".");
+        file_name.push(band);
+        PathBuf::from(file_name)
+    }
+
+    /// If the cached tags were created by another ctags version than the current one,
+    /// which is only considered with '--reindex-on-ctags-change'. Cached tags without
+    /// a recorded version are treated as created by another version.
+    fn ctags_version_changed(&self, config: &Config) -> bool {
+        if ! config.reindex_on_ctags_change {
+            return false;
+        }
+
+        match fs::read_to_string(self.ctags_version_file()) {
+            Ok(version) => version.trim() != config.ctags_version,
+            Err(_) => true
+        }
+    }
+
+    /// If the source files of a path dependency changed since its cached tags were created.
+    /// The source files are only read here, when the cached tags are checked, and not for
+    /// every source of the dependency tree. Cached tags without a recorded hash are treated
+    /// as created of other source files.
+    fn contents_changed(&self) -> bool {
+        if ! self.is_path_dependency {
+            return false;
+        }
+
+        match (fs::read_to_string(self.contents_hash_file()), contents_hash(&self.dir)) {
+            (Ok(recorded), Ok(hash)) => recorded.trim() != hash,
+            _ => true
+        }
     }
 
     pub fn recreate_status(&self, config: &Config) -> String {
@@ -340,12 +474,19 @@ impl Source {
             format!("Forced recreating of tags for {}", self.source_version())
         } else if self.is_root {
             format!("Recreating tags for cargo project root {}", self.source_version())
+        } else if config.impacted_by.as_ref() == Some(&self.name) {
+            format!("Recreating tags for {}, because it was given to '--impacted-by'", self.source_version())
         } else if ! self.cached_tags_file.is_file() {
             format!("Recreating tags for {}, because of missing cache file at '{:?}'",
                      self.source_version(), self.cached_tags_file)
         } else if ! self.tags_file.is_file() {
             format!("Recreating tags for {}, because of missing tags file at '{:?}'",
                      self.source_version(), self.tags_file)
+        } else if self.ctags_version_changed(config) {
+            format!("Recreating tags for {}, because its cached tags were created by another ctags version",
+                    self.source_version())
+        } else if self.contents_changed() {
+            format!("Recreating tags for {}, because its source files changed", self.source_version())
         } else {
             format!("Recreating tags for {}, because one of its dependencies was updated",
                     self.source_version())
@@ -385,6 +526,68 @@ impl<'a> SourceWithTmpTags<'a> {
     }
 }
 
+/// The module of a crate whose tags are kept by '--module-filter', e.g. 'tokio::net'.
+#[derive(Debug)]
+pub struct ModuleFilter {
+    /// the crate name with '_' instead of '-'
+    crate_name: String,
+
+    /// the path of the module in the crate, e.g. '["net"]'
+    pub module_path: Vec<String>
+}
+
+impl ModuleFilter {
+    pub fn parse(filter: &str) -> RtResult<ModuleFilter> {
+        let mut components = filter.split("::").map(str::trim);
+        let crate_name = components.next().unwrap_or("").replace('-', "_");
+        let module_path: Vec<String> = components.map(String::from).collect();
+        if crate_name.is_empty() || module_path.is_empty() || module_path.iter().any(|c| c.is_empty()) {
+            return Err(format!("Invalid module filter '{}', expected a crate and a module path like 'tokio::net'!", filter).into());
+        }
+
+        Ok(ModuleFilter { crate_name, module_path })
+    }
+
+    /// If the module is in the crate 'crate_name', a crate name of 'Cargo.toml'.
+    pub fn is_in_crate(&self, crate_name: &str) -> bool {
+        crate_name.replace('-', "_") == self.crate_name
+    }
+}
+
+/// The number of failed sources of '--continue-on-error' up to which
+/// a run still succeeds, given by '--max-failures'.
+#[derive(Debug, Clone, Copy)]
+pub enum MaxFailures {
+    /// a number of sources, e.g. '3'
+    Count(usize),
+
+    /// a percentage of the updated sources, e.g. '10%'
+    Percent(f64)
+}
+
+impl MaxFailures {
+    pub fn parse(max: &str) -> RtResult<MaxFailures> {
+        let max = max.trim();
+        let parsed = match max.strip_suffix('%') {
+            Some(pct) => pct.trim().parse::<f64>().ok()
+                .filter(|pct| *pct >= 0. && *pct <= 100.)
+                .map(MaxFailures::Percent),
+
+            None => max.parse::<usize>().ok().map(MaxFailures::Count)
+        };
+
+        parsed.ok_or_else(|| format!("Invalid maximal failures '{}', expected a number of sources like 3 or a percentage like 10%!", max).into())
+    }
+
+    /// If 'num_failed' of the 'num_updated' sources are more failures than allowed.
+    pub fn is_exceeded(&self, num_failed: usize, num_updated: usize) -> bool {
+        match *self {
+            MaxFailures::Count(max)   => num_failed > max,
+            MaxFailures::Percent(pct) => num_failed as f64 * 100. > pct * num_updated as f64
+        }
+    }
+}
+
 /// An unique runtime specific 'rusty-tags' internal id
 /// of the source.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
@@ -416,8 +619,15 @@ impl<'a> SourceVersion<'a> {
     }
 
     /// Parses an id from 'cargo metadata' (e.g "dtoa 0.4.2 (registry+https://github.com/rust-lang/crates.io-index)")
-    /// into a 'SourceVersion'.
+    /// into a 'SourceVersion'. Newer cargo versions use package id specs as ids, which are
+    /// also supported (e.g. "registry+https://github.com/rust-lang/crates.io-index#dtoa@0.4.2").
     pub fn parse_from_id(id: &'a str) -> RtResult<SourceVersion<'a>> {
+        if ! id.contains(' ') {
+            if let Some(hash_idx) = id.rfind('#') {
+                return SourceVersion::parse_from_id_spec(id, &id[..hash_idx], &id[hash_idx + 1..]);
+            }
+        }
+
         let mut split = id.split(' ');
         let name = split.next();
         if name == None {
@@ -433,6 +643,29 @@ impl<'a> SourceVersion<'a> {
 
         Ok(SourceVersion::new(name, Version::parse(version)?))
     }
+
+    /// Parses a package id spec 'id' with the source 'url' before the '#' and the 'fragment'
+    /// after it, which is 'name@version' or only 'version', if the name is the last path
+    /// segment of the url (e.g. "path+file:///home/dtoa#0.4.2").
+    fn parse_from_id_spec(id: &'a str, url: &'a str, fragment: &'a str) -> RtResult<SourceVersion<'a>> {
+        let (name, version) = match fragment.rfind('@') {
+            Some(at_idx) => (&fragment[..at_idx], &fragment[at_idx + 1..]),
+            None => {
+                let url = url.split('?').next().unwrap_or(url);
+                let path = url.split_once("://").map(|(_, path)| path).unwrap_or(url).trim_end_matches('/');
+                (path.rsplit('/').next().unwrap_or(path), fragment)
+            }
+        };
+
+        if name.is_empty() {
+            return Err(format!("Couldn't extract name from id: '{}'", id).into());
+        }
+
+        let version = Version::parse(version)
+            .map_err(|err| format!("Couldn't extract version from id: '{}': {}", id, err))?;
+
+        Ok(SourceVersion::new(name, version))
+    }
 }
 
 impl<'a> fmt::Debug for SourceVersion<'a> {
@@ -447,12 +680,79 @@ impl<'a> fmt::Display for SourceVersion<'a> {
     }
 }
 
-fn source_hash(source_dir: &Path) -> String {
-    let mut hasher = DefaultHasher::new();
-    source_dir.hash(&mut hasher);
+/// The hash of the identity of a source for the names of its cached files. Registry and git
+/// sources are identified by their name, version and cargo source, which contains the registry
+/// or the git revision, the contents of the source files of other sources like path dependencies
+/// are checked separately by 'Source::contents_changed'.
+///
+/// The directory of the source is always part of the hash, because the cached tags contain
+/// the absolute paths of the source files, so that the sources of different checkouts or
+/// cargo homes with the same contents don't share the cached tags with the paths of the other.
+///
+/// The options changing the cached tags are also part of the hash, so that tags created
+/// with different options aren't taken from the cache.
+fn source_hash(source_version: &SourceVersion, dir: &Path, cargo_source: Option<&str>, options: &str) -> String {
+    let mut hasher = FnvHasher::default();
+    if ! options.is_empty() {
+        hasher.write(options.as_bytes());
+        hasher.write(&[0]);
+    }
+
+    hasher.write(source_version.name.as_bytes());
+    hasher.write(&[0]);
+    hasher.write(source_version.version.to_string().as_bytes());
+    hasher.write(&[0]);
+    hasher.write(dir.to_string_lossy().as_bytes());
+
+    if let Some(cargo_source) = cargo_source {
+        hasher.write(&[0]);
+        hasher.write(cargo_source.as_bytes());
+    }
+
     hasher.finish().to_string()
 }
 
+/// The options changing the cached tags of the sources, which are empty for the
+/// default options, so that the hashes of the sources only change for other ones.
+fn tags_options(config: &Config) -> String {
+    let mut options = Vec::new();
+    options.extend(config.tags_spec.cached_tags_options());
+    options.extend(config.exclude_paths.iter().map(|r| format!("--exclude-path={}", r.as_str())));
+    if config.compact_deps {
+        options.push("--compact-deps".to_string());
+    }
+
+    if config.embed_provenance {
+        options.push("--embed-provenance".to_string());
+    }
+
+    if config.pseudo_tags != PseudoTags::Keep {
+        options.push(format!("--pseudo-tags={:?}", config.pseudo_tags));
+    }
+
+    if let Some(ref target) = config.active_cfg_target {
+        options.push(format!("--active-cfg-only={:?}", target));
+    }
+
+    if let Some(max_size) = config.max_file_size {
+        options.push(format!("--max-file-size={}", max_size));
+    }
+
+    options.join("\0")
+}
+
+/// The hash of the paths relative to 'dir' and of the contents of the rust source files in 'dir'.
+pub fn contents_hash(dir: &Path) -> RtResult<String> {
+    let mut hasher = FnvHasher::default();
+    for file in rust_files(dir, &[])? {
+        hasher.write(file.strip_prefix(dir).unwrap_or(&file).to_string_lossy().as_bytes());
+        hasher.write(&[0]);
+        hasher.write(&fs::read(&file)?);
+    }
+
+    Ok(hasher.finish().to_string())
+}
+
 // which kind of tags are created
 arg_enum! {
     #[derive(Eq, PartialEq, Debug)]
@@ -462,6 +762,64 @@ arg_enum! {
     }
 }
 
+// which pseudo tags of ctags are kept in the vi tags
+arg_enum! {
+    #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+    pub enum PseudoTags {
+        Keep,
+        Strip,
+        Minimal
+    }
+}
+
+// how warnings are formatted
+arg_enum! {
+    #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+    pub enum WarningFormat {
+        Text,
+        Quickfix
+    }
+}
+
+// how the cached tags are compressed
+arg_enum! {
+    #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+    pub enum CacheCompression {
+        None,
+        Gzip,
+        Zstd
+    }
+}
+
+// the line ending of the vi tags files
+arg_enum! {
+    #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+    pub enum LineEnding {
+        Lf,
+        Crlf,
+        Native
+    }
+}
+
+// the format of the vi tags files of the workspace members
+arg_enum! {
+    #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+    pub enum TagsFormat {
+        Full,
+        Minimal
+    }
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Native => if cfg!(windows) { "\r\n" } else { "\n" }
+        }
+    }
+}
+
 type ExeName = String;
 
 /// which ctags executable is used
@@ -485,11 +843,21 @@ pub struct TagsSpec {
     emacs_tags: String,
 
     /// options given to the ctags executable
-    ctags_options: String
+    ctags_options: String,
+
+    /// tag the public reexports with the kind 'reexport'
+    mark_reexports: bool,
+
+    /// command and arguments the ctags executable is run with
+    ctags_wrapper: Vec<String>,
+
+    /// the field spec given to '--fields' of the ctags executable
+    ctags_fields: Option<String>
 }
 
 impl TagsSpec {
-    pub fn new(kind: TagsKind, exe: TagsExe, vi_tags: String, emacs_tags: String, ctags_options: String) -> RtResult<TagsSpec> {
+    pub fn new(kind: TagsKind, exe: TagsExe, vi_tags: String, emacs_tags: String, ctags_options: String,
+               mark_reexports: bool, ctags_wrapper: Vec<String>) -> RtResult<TagsSpec> {
         if vi_tags == emacs_tags {
             return Err(format!("It's not supported to use the same tags name '{}' for vi and emacs!", vi_tags).into());
         }
@@ -499,10 +867,38 @@ impl TagsSpec {
             exe: exe,
             vi_tags: vi_tags,
             emacs_tags: emacs_tags,
-            ctags_options: ctags_options
+            ctags_options: ctags_options,
+            mark_reexports,
+            ctags_wrapper,
+            ctags_fields: None
         })
     }
 
+    /// Sets the field spec given to '--fields' of the ctags executable, e.g. '+nS'.
+    pub fn set_ctags_fields(&mut self, fields: String) -> RtResult<()> {
+        validate_ctags_fields(&fields, &self.exe)?;
+        self.ctags_fields = Some(fields);
+        Ok(())
+    }
+
+    /// The options of the ctags executable changing the cached tags of the sources.
+    pub fn cached_tags_options(&self) -> Vec<String> {
+        let mut options = Vec::new();
+        if ! self.ctags_options.is_empty() {
+            options.push(format!("--ctags-options={}", self.ctags_options));
+        }
+
+        if self.mark_reexports {
+            options.push("--mark-reexports".to_string());
+        }
+
+        if let Some(ref fields) = self.ctags_fields {
+            options.push(format!("--ctags-fields={}", fields));
+        }
+
+        options
+    }
+
     pub fn file_extension(&self) -> &'static str {
         match self.kind {
             TagsKind::Vi    => "vi",
@@ -520,7 +916,7 @@ impl TagsSpec {
     pub fn ctags_command(&self) -> Command {
         match self.exe {
             TagsExe::ExuberantCtags(ref exe_name) => {
-                let mut cmd = Command::new(&exe_name);
+                let mut cmd = self.new_command(exe_name);
                 self.generic_ctags_options(&mut cmd);
                 cmd.arg("--languages=Rust")
                    .arg("--langdef=Rust")
@@ -533,20 +929,47 @@ impl TagsSpec {
                    .arg("--regex-Rust=/^[ \\t]*(pub[ \\t]+)?(static|const)[ \\t]+([a-zA-Z0-9_]+)/\\3/c,consts,static constants/")
                    .arg("--regex-Rust=/^[ \\t]*(pub[ \\t]+)?(unsafe[ \\t]+)?trait[ \\t]+([a-zA-Z0-9_]+)/\\3/t,traits,traits/")
                    .arg("--regex-Rust=/^[ \\t]*macro_rules![ \\t]+([a-zA-Z0-9_]+)/\\1/d,macros,macro definitions/");
+                self.reexport_ctags_options(&mut cmd);
 
                 cmd
             }
 
             TagsExe::UniversalCtags(ref exe_name) => {
-                let mut cmd = Command::new(&exe_name);
+                let mut cmd = self.new_command(exe_name);
                 self.generic_ctags_options(&mut cmd);
                 cmd.arg("--languages=Rust");
+                self.reexport_ctags_options(&mut cmd);
 
                 cmd
             }
         }
     }
 
+    /// Creates the command running 'exe_name', which is prepended by
+    /// the wrapper command if one is configured.
+    fn new_command(&self, exe_name: &str) -> Command {
+        match self.ctags_wrapper.split_first() {
+            Some((wrapper, wrapper_args)) => {
+                let mut cmd = Command::new(wrapper);
+                cmd.args(wrapper_args).arg(exe_name);
+                cmd
+            }
+
+            None => Command::new(exe_name)
+        }
+    }
+
+    /// Tags the names introduced by 'pub use' with the kind 'reexport', so
+    /// that editors can prefer the real definition of the name.
+    fn reexport_ctags_options(&self, cmd: &mut Command) {
+        if ! self.mark_reexports {
+            return;
+        }
+
+        cmd.arg("--regex-Rust=/^[ \\t]*pub(\\([^)]*\\))?[ \\t]+use[ \\t]+([a-zA-Z0-9_]+::)*([a-zA-Z0-9_]+)[ \\t]*;/\\3/R,reexport,public reexports/")
+           .arg("--regex-Rust=/^[ \\t]*pub(\\([^)]*\\))?[ \\t]+use[ \\t]+[a-zA-Z0-9_:]+[ \\t]+as[ \\t]+([a-zA-Z0-9_]+)[ \\t]*;/\\2/R,reexport,public reexports/");
+    }
+
     fn generic_ctags_options(&self, cmd: &mut Command) {
         match self.kind {
             TagsKind::Vi    => {}
@@ -557,7 +980,61 @@ impl TagsSpec {
         if ! self.ctags_options.is_empty() {
             cmd.arg(&self.ctags_options);
         }
+
+        if let Some(ref fields) = self.ctags_fields {
+            cmd.arg(format!("--fields={}", fields));
+        }
+    }
+}
+
+/// Checks that 'fields' is a field spec of '--fields' of ctags like '+nS' or '+{signature}-{access}':
+/// one letter or a long name in braces - only supported by universal ctags - per field, each
+/// optionally preceded by '+' or '-', or '*' for all fields.
+fn validate_ctags_fields(fields: &str, exe: &TagsExe) -> RtResult<()> {
+    let invalid = |reason: &str| -> RtResult<()> {
+        Err(format!("Invalid ctags field spec '{}', {}!", fields, reason).into())
+    };
+
+    if fields.is_empty() {
+        return invalid("expected fields like '+nS' or '+{signature}'");
+    }
+
+    let mut chars = fields.chars();
+    let mut has_field = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '+' | '-' => has_field = false,
+            '*' => has_field = true,
+            '{' => {
+                if let TagsExe::ExuberantCtags(_) = *exe {
+                    return invalid("long field names in braces are only supported by universal ctags");
+                }
+
+                let rest = chars.as_str();
+                let end = match rest.find('}') {
+                    Some(end) => end,
+                    None => return invalid("missing closing brace")
+                };
+
+                let name = &rest[..end];
+                if name.is_empty() || ! name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+                    return invalid("expected a field name like '{signature}' in braces");
+                }
+
+                chars = rest[end + 1..].chars();
+                has_field = true;
+            }
+
+            c if c.is_ascii_alphabetic() => has_field = true,
+            c => return invalid(&format!("unexpected character '{}'", c))
+        }
     }
+
+    if ! has_field {
+        return invalid("expected a field after the last '+' or '-'");
+    }
+
+    Ok(())
 }
 
 pub fn unique_sources(sources: &mut Vec<&Source>) {
@@ -586,3 +1063,128 @@ fn find_dir_upwards_containing(file_name: &str, start_dir: &Path) -> RtResult<Pa
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn universal() -> TagsExe {
+        TagsExe::UniversalCtags("ctags".to_string())
+    }
+
+    fn exuberant() -> TagsExe {
+        TagsExe::ExuberantCtags("ctags".to_string())
+    }
+
+    #[test]
+    fn validate_ctags_fields_of_valid_specs() {
+        for fields in &["+nS", "n", "*", "-a+K", "+{signature}-{access}", "+{C.properties}", "+n{end}"] {
+            assert!(validate_ctags_fields(fields, &universal()).is_ok(), "{}", fields);
+        }
+
+        assert!(validate_ctags_fields("+nS-a", &exuberant()).is_ok());
+    }
+
+    #[test]
+    fn validate_ctags_fields_of_invalid_specs() {
+        for fields in &["", "+", "n-", "+{}", "+{signature", "+{sig nature}", "+n,S", "1"] {
+            assert!(validate_ctags_fields(fields, &universal()).is_err(), "{}", fields);
+        }
+
+        assert!(validate_ctags_fields("+{signature}", &exuberant()).is_err());
+    }
+
+    fn parsed_id(id: &str) -> (String, String) {
+        let source_version = SourceVersion::parse_from_id(id).unwrap();
+        (source_version.name.to_string(), source_version.version.to_string())
+    }
+
+    fn pair(name: &str, version: &str) -> (String, String) {
+        (name.to_string(), version.to_string())
+    }
+
+    #[test]
+    fn parse_from_id_of_valid_ids() {
+        assert_eq!(parsed_id("dtoa 0.4.2 (registry+https://github.com/rust-lang/crates.io-index)"), pair("dtoa", "0.4.2"));
+        assert_eq!(parsed_id("registry+https://github.com/rust-lang/crates.io-index#dtoa@0.4.2"), pair("dtoa", "0.4.2"));
+        assert_eq!(parsed_id("path+file:///home/dtoa#0.4.2"), pair("dtoa", "0.4.2"));
+        assert_eq!(parsed_id("path+file:///home/ws/crates/my-crate/#1.0.0-beta.1"), pair("my-crate", "1.0.0-beta.1"));
+        assert_eq!(parsed_id("git+https://github.com/serde-rs/json?branch=master#serde_json@1.0.80"), pair("serde_json", "1.0.80"));
+        assert_eq!(parsed_id("git+https://github.com/serde-rs/json?rev=abc#1.0.80"), pair("json", "1.0.80"));
+    }
+
+    #[test]
+    fn parse_from_id_of_invalid_ids() {
+        for id in &["dtoa", "dtoa abc (registry+https://github.com/rust-lang/crates.io-index)",
+                    "registry+https://github.com/rust-lang/crates.io-index#@0.4.2",
+                    "registry+https://github.com/rust-lang/crates.io-index#dtoa@",
+                    "path+file:///#0.4.2", "path+file:///home/dtoa#latest"] {
+            assert!(SourceVersion::parse_from_id(id).is_err(), "{}", id);
+        }
+    }
+
+    #[test]
+    fn module_filter_parse() {
+        let filter = ModuleFilter::parse("tokio-util::codec::length").unwrap();
+        assert_eq!(filter.module_path, vec!["codec".to_string(), "length".to_string()]);
+        assert!(filter.is_in_crate("tokio-util"));
+        assert!(filter.is_in_crate("tokio_util"));
+        assert!(! filter.is_in_crate("tokio"));
+
+        for filter in &["", "tokio", "tokio::", "::net", "tokio::::net", "tokio::net::"] {
+            assert!(ModuleFilter::parse(filter).is_err(), "{}", filter);
+        }
+    }
+
+    #[test]
+    fn max_failures_parse() {
+        match MaxFailures::parse("3").unwrap() {
+            MaxFailures::Count(count) => assert_eq!(count, 3),
+            max => panic!("unexpected {:?}", max)
+        }
+
+        match MaxFailures::parse(" 12.5 %").unwrap() {
+            MaxFailures::Percent(pct) => assert_eq!(pct, 12.5),
+            max => panic!("unexpected {:?}", max)
+        }
+
+        for max in &["", "-1", "1.5", "abc", "%", "101%", "-5%", "NaN%"] {
+            assert!(MaxFailures::parse(max).is_err(), "{}", max);
+        }
+    }
+
+    #[test]
+    fn max_failures_is_exceeded() {
+        assert!(! MaxFailures::Count(2).is_exceeded(2, 10));
+        assert!(MaxFailures::Count(2).is_exceeded(3, 10));
+        assert!(! MaxFailures::Percent(10.).is_exceeded(1, 10));
+        assert!(MaxFailures::Percent(10.).is_exceeded(2, 10));
+        assert!(MaxFailures::Percent(0.).is_exceeded(1, 0));
+    }
+
+    #[test]
+    fn source_hash_differs_for_dirs_with_same_contents() {
+        let checkout_a = tempfile::tempdir().unwrap();
+        let checkout_b = tempfile::tempdir().unwrap();
+        for checkout in &[&checkout_a, &checkout_b] {
+            fs::create_dir(checkout.path().join("src")).unwrap();
+            fs::write(checkout.path().join("src").join("lib.rs"), "pub fn dep() {}\n").unwrap();
+        }
+
+        assert_eq!(contents_hash(checkout_a.path()).unwrap(), contents_hash(checkout_b.path()).unwrap());
+
+        let path_dep = SourceVersion::parse_from_id("path+file:///home/dep#0.1.0").unwrap();
+        assert_ne!(source_hash(&path_dep, checkout_a.path(), None, ""),
+                   source_hash(&path_dep, checkout_b.path(), None, ""));
+
+        let registry = "registry+https://github.com/rust-lang/crates.io-index";
+        let registry_dep = SourceVersion::parse_from_id("registry+https://github.com/rust-lang/crates.io-index#dep@0.1.0").unwrap();
+        assert_ne!(source_hash(&registry_dep, checkout_a.path(), Some(registry), ""),
+                   source_hash(&registry_dep, checkout_b.path(), Some(registry), ""));
+
+        assert_eq!(source_hash(&path_dep, checkout_a.path(), None, ""),
+                   source_hash(&path_dep, checkout_a.path(), None, ""));
+        assert_ne!(source_hash(&path_dep, checkout_a.path(), None, ""),
+                   source_hash(&path_dep, checkout_a.path(), None, "--compact-deps"));
+    }
+}
@@ -0,0 +1,164 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use fnv::FnvHashMap;
+use sha2::{Digest, Sha256};
+use serde_json::Value as JsonValue;
+use toml;
+
+use rt_result::RtResult;
+use types::SourceVersion;
+use config::Config;
+use output::Location;
+
+/// The checksums of the registry sources in the 'Cargo.lock' files of
+/// the workspaces, by the name and the version of the source.
+pub type LockChecksums = FnvHashMap<(String, String), String>;
+
+#[derive(Deserialize, Debug)]
+struct Lockfile {
+    package: Option<Vec<LockPackage>>,
+
+    /// only lockfiles of the first format version contain
+    /// the checksums as 'checksum <name> <version> (<source>)' keys
+    metadata: Option<FnvHashMap<String, String>>
+}
+
+#[derive(Deserialize, Debug)]
+struct LockPackage {
+    name: String,
+    version: String,
+    checksum: Option<String>
+}
+
+/// Reads the checksums of the 'Cargo.lock' files in the workspace roots of 'metadatas'.
+/// A missing 'Cargo.lock' only results into a missing checksum for its sources.
+pub fn lock_checksums(config: &Config, metadatas: &[&JsonValue]) -> RtResult<LockChecksums> {
+    let mut checksums = LockChecksums::default();
+    for metadata in metadatas {
+        let lock_file = match metadata.get("workspace_root").and_then(JsonValue::as_str) {
+            Some(root) => Path::new(root).join("Cargo.lock"),
+            None => continue
+        };
+
+        if ! lock_file.is_file() {
+            verbose!(config, "No checksums for the verification of the sources, missing '{}'", lock_file.display());
+            continue;
+        }
+
+        let contents = fs::read_to_string(&lock_file)?;
+        let lockfile: Lockfile = toml::from_str(&contents)
+            .map_err(|err| format!("Couldn't parse '{}': {}", lock_file.display(), err))?;
+
+        for package in lockfile.package.unwrap_or_default() {
+            if let Some(checksum) = package.checksum {
+                checksums.insert((package.name, package.version), checksum);
+            }
+        }
+
+        for (key, checksum) in lockfile.metadata.unwrap_or_default() {
+            let mut parts = key.split_whitespace();
+            if let (Some("checksum"), Some(name), Some(version)) = (parts.next(), parts.next(), parts.next()) {
+                checksums.insert((name.to_string(), version.to_string()), checksum);
+            }
+        }
+    }
+
+    Ok(checksums)
+}
+
+/// Verifies the registry source 'source_version' unpacked at 'manifest_dir' before it's
+/// indexed, the sources of other kinds, like path or git dependencies, aren't verified.
+///
+/// The unpacked source has to contain the '.cargo-ok' marker, which cargo writes after the
+/// complete extraction of the '.crate' archive, and the archive in the cache of the registry
+/// has to match the checksum of 'Cargo.lock'. Only warnings are printed for mismatches.
+pub fn verify_source(config: &Config,
+                     package: &JsonValue,
+                     source_version: &SourceVersion,
+                     manifest_dir: &Path,
+                     checksums: &LockChecksums)
+                     -> RtResult<()> {
+    let is_registry = package.get("source")
+        .and_then(JsonValue::as_str)
+        .map(|s| s.starts_with("registry+") || s.starts_with("sparse+"))
+        .unwrap_or(false);
+
+    if ! is_registry {
+        return Ok(());
+    }
+
+    let name = source_version.name;
+    let cargo_ok = manifest_dir.join(".cargo-ok");
+    match fs::read_to_string(&cargo_ok) {
+        // older cargo versions write 'ok' and newer ones '{"v":1}'
+        Ok(ref contents) if contents.trim() == "ok" || contents.trim().starts_with("{\"v\":") => {}
+        Ok(contents) => warning!(config, Location::File(&cargo_ok, None),
+                                 "Unexpected contents '{}' of the extraction marker of {}", contents.trim(), source_version),
+        Err(_) => warning!(config, Location::Crate(name),
+                           "Missing '.cargo-ok' in '{}', the source might be incompletely extracted", manifest_dir.display())
+    }
+
+    let version = source_version.version.to_string();
+    let checksum = match checksums.get(&(name.to_string(), version.clone())) {
+        Some(checksum) => checksum,
+        None => {
+            verbose!(config, "No checksum in 'Cargo.lock' for {}", source_version);
+            return Ok(());
+        }
+    };
+
+    // the sources are unpacked at '<registry>/src/<index>/<name>-<version>'
+    // and their archives are at '<registry>/cache/<index>/<name>-<version>.crate'
+    let archive = {
+        let index_dir = manifest_dir.parent();
+        let index = index_dir.and_then(Path::file_name);
+        let registry_dir = index_dir.and_then(Path::parent).and_then(Path::parent);
+        match (registry_dir, index) {
+            (Some(registry_dir), Some(index)) => registry_dir.join("cache").join(index).join(format!("{}-{}.crate", name, version)),
+            _ => return Ok(())
+        }
+    };
+
+    if ! archive.is_file() {
+        verbose!(config, "Couldn't verify the checksum of {}, missing archive '{}'", source_version, archive.display());
+        return Ok(());
+    }
+
+    let archive_checksum = sha256(&archive)?;
+    if archive_checksum != *checksum {
+        warning!(config, Location::File(&archive, None),
+                 "Checksum mismatch of {}: expected '{}' of 'Cargo.lock' but found '{}'", source_version, checksum, archive_checksum);
+    } else {
+        verbose!(config, "Verified checksum of {}", source_version);
+    }
+
+    Ok(())
+}
+
+/// the SHA-256 checksum of the contents of 'file' as lowercase hex digits
+fn sha256(file: &Path) -> RtResult<String> {
+    let mut reader = File::open(file)
+        .map_err(|err| format!("Couldn't open '{}' for the verification of the sources: {}", file.display(), err))?;
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)
+        .map_err(|err| format!("Couldn't read '{}' for the verification of the sources: {}", file.display(), err))?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_of_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("dep-0.1.0.crate");
+        fs::write(&file, "abc").unwrap();
+        assert_eq!(sha256(&file).unwrap(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert!(sha256(&dir.path().join("missing.crate")).is_err());
+    }
+}